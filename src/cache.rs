@@ -0,0 +1,272 @@
+// Persistent cache of signature addresses, so that unchanged modules don't have to be rescanned on
+// every launch.
+//
+// The file is kept next to the DLL and has one tab-separated entry per line:
+// <timestamp> <image size> <code checksum> <signature name> <variant name> <RVA>
+
+use moduleinfo::ModuleInfo;
+use moduleinfo::pe::{Layout, Pe};
+use moduleinfo::provider::ModuleProvider;
+use moduleinfo::windows;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use utils;
+
+const CACHE_FILE: &'static str = "hls-autopause-cache.txt";
+
+const FNV_OFFSET_BASIS: u32 = 0x811C9DC5;
+const FNV_PRIME: u32 = 0x01000193;
+
+// Identifies a specific build of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModuleKey {
+    pub timestamp: u32,
+    pub size_of_image: u32,
+    pub code_checksum: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    variant: String,
+    rva: usize,
+}
+
+struct Cache {
+    path: PathBuf,
+    entries: HashMap<(ModuleKey, String), Entry>,
+    // Whether the entries changed since the file was written.
+    dirty: bool,
+    // Checksumming the code of a big module isn't free, remember it for every loaded module.
+    checksums: HashMap<(usize, u32, u32), u32>,
+}
+
+lazy_static! {
+    static ref CACHE: RwLock<Cache> = RwLock::new(Cache::load());
+}
+
+impl Cache {
+    fn load() -> Cache {
+        let path = match utils::own_directory() {
+            Some(directory) => directory.join(CACHE_FILE),
+            None => {
+                warn!(target: "cache", "Couldn't get the directory of the DLL");
+                PathBuf::from(CACHE_FILE)
+            }
+        };
+
+        let mut cache = Cache {
+            path: path,
+            entries: HashMap::new(),
+            dirty: false,
+            checksums: HashMap::new(),
+        };
+
+        let file = match File::open(&cache.path) {
+            Ok(file) => file,
+            Err(_) => return cache,
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            match parse_line(&line) {
+                Some((key, name, entry)) => {
+                    cache.entries.insert((key, name), entry);
+                }
+                None => warn!(target: "cache", "Skipping invalid line: {}", line),
+            }
+        }
+
+        debug!(target: "cache",
+               "Loaded {} entries from {}",
+               cache.entries.len(),
+               cache.path.display());
+
+        cache
+    }
+
+    // Only the entries of loaded modules are written, so that the builds that aren't used anymore
+    // don't stay in the file forever.
+    fn save(&mut self) {
+        let loaded = windows::WINDOWS.get_loaded()
+                                     .into_iter()
+                                     .map(|module_info| module_info.base as usize)
+                                     .collect::<Vec<_>>();
+        let keys = self.checksums
+                       .iter()
+                       .filter(|&(&(base, _, _), _)| loaded.contains(&base))
+                       .map(|(&(_, timestamp, size_of_image), &code_checksum)| {
+                           ModuleKey {
+                               timestamp: timestamp,
+                               size_of_image: size_of_image,
+                               code_checksum: code_checksum,
+                           }
+                       })
+                       .collect::<Vec<_>>();
+
+        let mut contents = String::new();
+
+        for (&(ref key, ref name), entry) in self.entries
+                                                 .iter()
+                                                 .filter(|&(&(ref key, _), _)| keys.contains(key)) {
+            contents.push_str(&format!("{:08X}\t{:08X}\t{:08X}\t{}\t{}\t{:08X}\n",
+                                       key.timestamp,
+                                       key.size_of_image,
+                                       key.code_checksum,
+                                       name,
+                                       entry.variant,
+                                       entry.rva));
+        }
+
+        match File::create(&self.path).and_then(|mut file| file.write_all(contents.as_bytes())) {
+            Ok(()) => self.dirty = false,
+            Err(err) => warn!(target: "cache", "Error writing {}: {}", self.path.display(), err),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(ModuleKey, String, Entry)> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+
+    if fields.len() != 6 {
+        return None;
+    }
+
+    let hex = |s: &str| u32::from_str_radix(s, 16).ok();
+
+    let key = ModuleKey {
+        timestamp: match hex(fields[0]) { Some(x) => x, None => return None },
+        size_of_image: match hex(fields[1]) { Some(x) => x, None => return None },
+        code_checksum: match hex(fields[2]) { Some(x) => x, None => return None },
+    };
+
+    let rva = match hex(fields[5]) {
+        Some(rva) => rva as usize,
+        None => return None,
+    };
+
+    Some((key,
+          fields[3].to_owned(),
+          Entry {
+              variant: fields[4].to_owned(),
+              rva: rva,
+          }))
+}
+
+// FNV-1a, continuing from the hash of whatever came before the data.
+fn checksum(hash: u32, data: &[u8]) -> u32 {
+    data.iter().fold(hash, |hash, &b| (hash ^ b as u32).wrapping_mul(FNV_PRIME))
+}
+
+// Checksums the code in the module's file rather than in memory, where it's relocated when the
+// module isn't loaded at its preferred base and patched by hooks.
+fn checksum_file_code(module_info: &ModuleInfo) -> Option<u32> {
+    let path = match windows::WINDOWS.get_module_path(module_info.handle) {
+        Some(path) => path,
+        None => return None,
+    };
+
+    let mut file = Vec::new();
+    if let Err(err) = File::open(&path).and_then(|mut f| f.read_to_end(&mut file)) {
+        warn!(target: "cache", "Error reading {}: {}", path.display(), err);
+        return None;
+    }
+
+    let pe = match Pe::parse(&file, Layout::File) {
+        Ok(pe) => pe,
+        Err(err) => {
+            warn!(target: "cache", "Error parsing {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    let code_checksum = pe.sections
+                          .iter()
+                          .filter(|section| section.is_executable())
+                          .fold(FNV_OFFSET_BASIS,
+                                |hash, section| checksum(hash, pe.section_data(section)));
+
+    Some(code_checksum)
+}
+
+pub fn module_key(module_info: &ModuleInfo) -> Option<ModuleKey> {
+    let pe = match module_info.pe() {
+        Ok(pe) => pe,
+        Err(_) => return None,
+    };
+
+    let checksum_key = (module_info.base as usize, pe.timestamp, pe.size_of_image);
+
+    if let Some(&code_checksum) = CACHE.read().unwrap().checksums.get(&checksum_key) {
+        return Some(ModuleKey {
+            timestamp: pe.timestamp,
+            size_of_image: pe.size_of_image,
+            code_checksum: code_checksum,
+        });
+    }
+
+    let code_checksum = match checksum_file_code(module_info) {
+        Some(code_checksum) => code_checksum,
+        None => return None,
+    };
+
+    let key = ModuleKey {
+        timestamp: pe.timestamp,
+        size_of_image: pe.size_of_image,
+        code_checksum: code_checksum,
+    };
+
+    let mut cache = CACHE.write().unwrap();
+    cache.checksums.insert(checksum_key, code_checksum);
+
+    // If the file was written before the module got loaded, its entries were left out.
+    if cache.entries.keys().any(|&(ref entry_key, _)| *entry_key == key) {
+        cache.dirty = true;
+    }
+
+    Some(key)
+}
+
+// Returns the cached RVA and the name of the variant that matched there.
+pub fn lookup(key: &ModuleKey, name: &str) -> Option<(usize, String)> {
+    CACHE.read()
+         .unwrap()
+         .entries
+         .get(&(*key, name.to_owned()))
+         .map(|entry| (entry.rva, entry.variant.clone()))
+}
+
+pub fn store(key: &ModuleKey, name: &str, variant: &str, rva: usize) {
+    let mut cache = CACHE.write().unwrap();
+
+    let entry = Entry {
+        variant: variant.to_owned(),
+        rva: rva,
+    };
+
+    cache.entries.insert((*key, name.to_owned()), entry);
+    cache.dirty = true;
+}
+
+pub fn remove(key: &ModuleKey, name: &str) {
+    let mut cache = CACHE.write().unwrap();
+
+    if cache.entries.remove(&(*key, name.to_owned())).is_some() {
+        cache.dirty = true;
+    }
+}
+
+// Writes the file if anything was stored or removed since the last time.
+pub fn flush() {
+    let mut cache = CACHE.write().unwrap();
+
+    if cache.dirty {
+        cache.save();
+    }
+}
@@ -0,0 +1,213 @@
+// The pure-Rust inline hooker. The start of the target is replaced with a jump to the detour and
+// the overwritten instructions are moved to a trampoline, see trampoline.rs.
+//
+// Unlike MinHook, other threads aren't suspended while the code is patched, so hooks should be
+// applied before the target can run, like when its module has just been loaded.
+
+use kernel32;
+use std::{error, fmt, io, ptr, result, slice};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::{Detour, Result};
+use super::trampoline::{self, JUMP_LEN, MAX_CODE_LEN};
+use utils;
+use winapi::*;
+
+// Every trampoline gets its own allocation, the hooks are few.
+const TRAMPOLINE_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub enum Error {
+    AlreadyCreated,
+    NotCreated,
+    Trampoline(trampoline::Error),
+    Memory(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Trampoline(ref err) => write!(f, "can't build the trampoline: {}", err),
+            Error::Memory(ref err) => write!(f, "memory error: {}", err),
+            _ => write!(f, "{}", (self as &error::Error).description()),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::AlreadyCreated => "the hook is already created",
+            Error::NotCreated => "the hook is not created",
+            Error::Trampoline(ref err) => err.description(),
+            Error::Memory(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Trampoline(ref err) => Some(err),
+            Error::Memory(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+struct Hook {
+    detour: usize,
+    trampoline: usize,
+    // The bytes the jump to the detour overwrites.
+    original: [u8; JUMP_LEN],
+    enabled: bool,
+    queued: Option<bool>,
+}
+
+pub struct Inline {
+    hooks: Mutex<HashMap<usize, Hook>>,
+}
+
+lazy_static! {
+    pub static ref INLINE: Inline = Inline { hooks: Mutex::new(HashMap::new()) };
+}
+
+fn set_enabled(target: usize, hook: &mut Hook, enabled: bool) -> result::Result<(), Error> {
+    if hook.enabled == enabled {
+        return Ok(());
+    }
+
+    let code = if enabled {
+        trampoline::jump(target, hook.detour)
+    } else {
+        hook.original
+    };
+
+    try!(unsafe { utils::write_memory(target, &code) }.map_err(Error::Memory));
+    hook.enabled = enabled;
+
+    Ok(())
+}
+
+impl Inline {
+    // Calls the function on the hook with the target, or on every hook.
+    fn for_hooks<F>(&self, target: Option<LPVOID>, mut f: F) -> Result<()>
+        where F: FnMut(usize, &mut Hook) -> result::Result<(), Error>
+    {
+        let mut hooks = self.hooks.lock().unwrap();
+
+        match target {
+            Some(target) => {
+                let hook = try!(hooks.get_mut(&(target as usize)).ok_or(Error::NotCreated));
+                try!(f(target as usize, hook));
+            }
+            None => {
+                for (&target, hook) in hooks.iter_mut() {
+                    try!(f(target, hook));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Detour for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn create_hook(&self, target: LPVOID, detour: LPVOID) -> Result<LPVOID> {
+        let mut hooks = self.hooks.lock().unwrap();
+
+        if hooks.contains_key(&(target as usize)) {
+            return Err(Box::new(Error::AlreadyCreated));
+        }
+
+        let memory = unsafe {
+            kernel32::VirtualAlloc(ptr::null_mut(),
+                                   TRAMPOLINE_SIZE as SIZE_T,
+                                   MEM_COMMIT | MEM_RESERVE,
+                                   PAGE_EXECUTE_READWRITE)
+        };
+        if memory.is_null() {
+            return Err(Box::new(Error::Memory(io::Error::last_os_error())));
+        }
+
+        let code = unsafe { slice::from_raw_parts(target as *const u8, MAX_CODE_LEN) };
+
+        let built = match trampoline::build(code, target as usize, memory as usize) {
+            Ok(built) => built,
+            Err(err) => {
+                unsafe {
+                    kernel32::VirtualFree(memory, 0, MEM_RELEASE);
+                }
+                return Err(Box::new(Error::Trampoline(err)));
+            }
+        };
+
+        unsafe {
+            ptr::copy_nonoverlapping(built.code.as_ptr(), memory as *mut u8, built.code.len());
+        }
+
+        let mut original = [0; JUMP_LEN];
+        original.copy_from_slice(&code[..JUMP_LEN]);
+
+        hooks.insert(target as usize,
+                     Hook {
+                         detour: detour as usize,
+                         trampoline: memory as usize,
+                         original: original,
+                         enabled: false,
+                         queued: None,
+                     });
+
+        Ok(memory)
+    }
+
+    fn remove_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        try!(self.for_hooks(target, |target, hook| set_enabled(target, hook, false)));
+
+        let mut hooks = self.hooks.lock().unwrap();
+
+        let removed: Vec<Hook> = match target {
+            Some(target) => hooks.remove(&(target as usize)).into_iter().collect(),
+            None => hooks.drain().map(|(_, hook)| hook).collect(),
+        };
+
+        for hook in removed {
+            unsafe {
+                kernel32::VirtualFree(hook.trampoline as LPVOID, 0, MEM_RELEASE);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn queue_enable_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        self.for_hooks(target, |_, hook| {
+            hook.queued = Some(true);
+            Ok(())
+        })
+    }
+
+    fn queue_disable_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        self.for_hooks(target, |_, hook| {
+            hook.queued = Some(false);
+            Ok(())
+        })
+    }
+
+    fn apply_queued(&self) -> Result<()> {
+        self.for_hooks(None, |target, hook| {
+            match hook.queued.take() {
+                Some(enabled) => set_enabled(target, hook, enabled),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn uninitialize(&self) {
+        if let Err(err) = self.remove_hook(None) {
+            error!(target: "detour", "Error removing the inline hooks: {}", err);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// The MinHook library.
+
+use libc;
+use std::{error, fmt, ptr};
+use std::ffi::CStr;
+use super::{Detour, Result};
+use winapi::*;
+
+#[allow(non_camel_case_types)]
+type MH_STATUS = i32;
+const MH_OK: MH_STATUS = 0;
+
+#[link(name = "MinHook", kind = "static")]
+extern "system" {
+    fn MH_Initialize() -> MH_STATUS;
+    fn MH_Uninitialize() -> MH_STATUS;
+    fn MH_CreateHook(pTarget: LPVOID, pDetour: LPVOID, ppOriginal: *mut LPVOID) -> MH_STATUS;
+    fn MH_RemoveHook(pTarget: LPVOID) -> MH_STATUS;
+    fn MH_QueueEnableHook(pTarget: LPVOID) -> MH_STATUS;
+    fn MH_QueueDisableHook(pTarget: LPVOID) -> MH_STATUS;
+    fn MH_ApplyQueued() -> MH_STATUS;
+    fn MH_StatusToString(status: MH_STATUS) -> *const libc::c_char;
+}
+
+fn status_to_string(status: MH_STATUS) -> String {
+    unsafe { CStr::from_ptr(MH_StatusToString(status)).to_string_lossy().into_owned() }
+}
+
+#[derive(Debug)]
+pub struct MinHookError {
+    status: MH_STATUS,
+    description: String,
+}
+
+impl fmt::Display for MinHookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.description)
+    }
+}
+
+impl error::Error for MinHookError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl MinHookError {
+    fn new(status: MH_STATUS) -> Self {
+        MinHookError {
+            status: status,
+            description: status_to_string(status),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<'a> {
+    InitError(&'a MinHookError),
+    OperationError(MinHookError),
+}
+
+impl<'a> fmt::Display for Error<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InitError(_) => {
+                write!(f, "MinHook initialization error: {}", (self as &error::Error).description())
+            }
+            Error::OperationError(_) => write!(f, "{}", (self as &error::Error).description()),
+        }
+    }
+}
+
+impl<'a> error::Error for Error<'a> {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InitError(e) => &e.description,
+            Error::OperationError(ref e) => &e.description,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::InitError(e) => Some(e),
+            Error::OperationError(ref e) => Some(e),
+        }
+    }
+}
+
+lazy_static! {
+    static ref mh_init_result: ::std::result::Result<(), MinHookError> = unsafe {
+        match MH_Initialize() {
+            MH_OK => Ok(()),
+            err => Err(MinHookError::new(err))
+        }
+    };
+}
+
+pub struct MinHook;
+
+pub static MINHOOK: MinHook = MinHook;
+
+fn check_init() -> Result<()> {
+    match *mh_init_result {
+        Ok(()) => Ok(()),
+        Err(ref err) => Err(Box::new(Error::InitError(err))),
+    }
+}
+
+fn check(status: MH_STATUS) -> Result<()> {
+    match status {
+        MH_OK => Ok(()),
+        err => Err(Box::new(Error::OperationError(MinHookError::new(err)))),
+    }
+}
+
+impl Detour for MinHook {
+    fn name(&self) -> &'static str {
+        "MinHook"
+    }
+
+    fn create_hook(&self, target: LPVOID, detour: LPVOID) -> Result<LPVOID> {
+        try!(check_init());
+
+        let mut trampoline = ptr::null_mut();
+        try!(check(unsafe { MH_CreateHook(target, detour, &mut trampoline) }));
+
+        Ok(trampoline)
+    }
+
+    fn remove_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        try!(check_init());
+        check(unsafe { MH_RemoveHook(target.unwrap_or(ptr::null_mut())) })
+    }
+
+    fn queue_enable_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        try!(check_init());
+        check(unsafe { MH_QueueEnableHook(target.unwrap_or(ptr::null_mut())) })
+    }
+
+    fn queue_disable_hook(&self, target: Option<LPVOID>) -> Result<()> {
+        try!(check_init());
+        check(unsafe { MH_QueueDisableHook(target.unwrap_or(ptr::null_mut())) })
+    }
+
+    fn apply_queued(&self) -> Result<()> {
+        try!(check_init());
+        check(unsafe { MH_ApplyQueued() })
+    }
+
+    fn uninitialize(&self) {
+        if mh_init_result.is_ok() {
+            unsafe {
+                MH_Uninitialize();
+            }
+        }
+    }
+}
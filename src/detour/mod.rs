@@ -0,0 +1,112 @@
+// Hooking functions by patching their code. The hook! and unhook! macros go through the functions
+// here, which forward to the backend chosen with the Y_DETOUR environment variable: `minhook`
+// (the default) or `inline`.
+
+use std::{env, error};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use winapi::*;
+
+pub mod inline;
+pub mod minhook;
+pub mod trampoline;
+
+pub type Result<T> = ::std::result::Result<T, Box<error::Error>>;
+
+// Hooks are identified by their target. None stands for every hook.
+pub trait Detour: Sync {
+    fn name(&self) -> &'static str;
+    // Returns the trampoline, which calls the original function.
+    fn create_hook(&self, target: LPVOID, detour: LPVOID) -> Result<LPVOID>;
+    fn remove_hook(&self, target: Option<LPVOID>) -> Result<()>;
+    fn queue_enable_hook(&self, target: Option<LPVOID>) -> Result<()>;
+    fn queue_disable_hook(&self, target: Option<LPVOID>) -> Result<()>;
+    fn apply_queued(&self) -> Result<()>;
+    // Removes every hook.
+    fn uninitialize(&self);
+}
+
+lazy_static! {
+    static ref BACKEND: &'static Detour = match env::var("Y_DETOUR") {
+        Ok(ref name) if name == "inline" => &*inline::INLINE,
+        _ => &minhook::MINHOOK,
+    };
+
+    static ref trampoline_to_target: RwLock<HashMap<usize, usize>> = RwLock::new(HashMap::new());
+}
+
+pub fn backend() -> &'static Detour {
+    *BACKEND
+}
+
+pub fn uninitialize() {
+    backend().uninitialize();
+}
+
+// Stores the trampoline as the original function pointer. The pointer is left alone on error.
+pub fn create_hook<F: Copy>(target: LPVOID, detour: F, trampoline: &mut F) -> Result<()> {
+    let detour = unsafe { *(&detour as *const F as *const LPVOID) };
+    let original = try!(backend().create_hook(target, detour));
+
+    unsafe {
+        *(trampoline as *mut F as *mut LPVOID) = original;
+    }
+
+    trampoline_to_target.write().unwrap().insert(original as usize, target as usize);
+
+    Ok(())
+}
+
+fn target_of(trampoline: LPVOID) -> Result<LPVOID> {
+    match trampoline_to_target.read().unwrap().get(&(trampoline as usize)) {
+        Some(&target) => Ok(target as LPVOID),
+        None => Err(From::from(format!("no hook has the trampoline {:p}", trampoline))),
+    }
+}
+
+pub fn remove_hook(trampoline: Option<LPVOID>) -> Result<()> {
+    let target = match trampoline {
+        Some(trampoline) => Some(try!(target_of(trampoline))),
+        None => None,
+    };
+    try!(backend().remove_hook(target));
+
+    let mut map = trampoline_to_target.write().unwrap();
+    match trampoline {
+        Some(addr) => {
+            map.remove(&(addr as usize));
+        }
+        None => map.clear(),
+    }
+
+    Ok(())
+}
+
+pub fn queue_enable_hook(target: Option<LPVOID>) -> Result<()> {
+    backend().queue_enable_hook(target)
+}
+
+pub fn queue_disable_hook(trampoline: Option<LPVOID>) -> Result<()> {
+    let target = match trampoline {
+        Some(trampoline) => Some(try!(target_of(trampoline))),
+        None => None,
+    };
+    backend().queue_disable_hook(target)
+}
+
+// Queues turning the hook with the trampoline on or off. Returns the target.
+pub fn queue_set_enabled(trampoline: LPVOID, enabled: bool) -> Result<LPVOID> {
+    let target = try!(target_of(trampoline));
+
+    if enabled {
+        try!(backend().queue_enable_hook(Some(target)));
+    } else {
+        try!(backend().queue_disable_hook(Some(target)));
+    }
+
+    Ok(target)
+}
+
+pub fn apply_queued() -> Result<()> {
+    backend().apply_queued()
+}
@@ -0,0 +1,418 @@
+// Building the trampolines of inline hooks. The instructions that the jump to the detour overwrites
+// are moved to the trampoline, followed by a jump back to the rest of the function. Nothing here
+// touches memory, so it works with any buffer at any address.
+
+use std::{error, fmt};
+use x86;
+
+// `jmp rel32`.
+pub const JUMP_LEN: usize = 5;
+// Enough code to decode everything the jump overwrites: the last instruction starts before
+// JUMP_LEN and instructions are at most 15 bytes long.
+pub const MAX_CODE_LEN: usize = JUMP_LEN - 1 + 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // The instruction at the offset can't be decoded.
+    Undecodable(usize),
+    // The instruction at the offset can't be moved, like a 16-bit relative jump or a jump into
+    // the middle of another moved instruction.
+    Unmovable(usize),
+    // The function ends at the offset, before there's room for the jump.
+    TooShort(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Undecodable(offset) => write!(f, "undecodable instruction at +{:#x}", offset),
+            Error::Unmovable(offset) => write!(f, "can't move the instruction at +{:#x}", offset),
+            Error::TooShort(offset) => write!(f, "the function ends at +{:#x}", offset),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Undecodable(_) => "undecodable instruction",
+            Error::Unmovable(_) => "unmovable instruction",
+            Error::TooShort(_) => "the function is too short",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trampoline {
+    pub code: Vec<u8>,
+    // How many bytes at the start of the function the trampoline replaces, at least JUMP_LEN.
+    pub stolen: usize,
+}
+
+// How a moved instruction is written to the trampoline.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Branch {
+    // Copied as is, it doesn't refer to its own address.
+    None,
+    // `call rel32`, `jmp rel32` or `jcc rel32`, only the displacement changes.
+    Near,
+    // `jmp rel8`, becomes `jmp rel32`.
+    Short,
+    // `jcc rel8`, becomes `jcc rel32`.
+    ShortConditional,
+    // `loop`, `loope`, `loopne` and `jecxz` only have a rel8 form, so they jump over a `jmp rel32`
+    // to the destination.
+    Loop,
+}
+
+struct Moved {
+    offset: usize,
+    len: usize,
+    // The prefixes and the opcode, before the displacement.
+    opcode_end: usize,
+    branch: Branch,
+    destination: usize,
+    new_offset: usize,
+    new_len: usize,
+}
+
+pub fn jump(from: usize, to: usize) -> [u8; JUMP_LEN] {
+    let rel = rel32(from + JUMP_LEN, to);
+    [0xE9, rel[0], rel[1], rel[2], rel[3]]
+}
+
+fn rel32(from: usize, to: usize) -> [u8; 4] {
+    let rel = to.wrapping_sub(from) as u32;
+    [rel as u8, (rel >> 8) as u8, (rel >> 16) as u8, (rel >> 24) as u8]
+}
+
+fn is_padding(byte: u8) -> bool {
+    byte == 0xCC || byte == 0x90 || byte == 0x00
+}
+
+// Builds the trampoline for the code at `target`, to be placed at `trampoline`. The code needs to
+// have at least MAX_CODE_LEN bytes unless the function is shorter.
+pub fn build(code: &[u8], target: usize, trampoline: usize) -> Result<Trampoline, Error> {
+    let mut moved: Vec<Moved> = Vec::new();
+    let mut offset = 0;
+    // Whether the moved code ends with a return or an unconditional jump.
+    let mut ends = false;
+
+    while offset < JUMP_LEN {
+        let instruction = try!(x86::decode(&code[offset..]).ok_or(Error::Undecodable(offset)));
+        let bytes = &code[offset..offset + instruction.len];
+
+        let (branch, opcode_end, destination) = match instruction.rel {
+            Some(rel) => {
+                let opcode = bytes[rel.offset - 1];
+                let branch = match (rel.size, opcode) {
+                    (4, _) => Branch::Near,
+                    (1, 0xEB) => Branch::Short,
+                    (1, 0x70...0x7F) => Branch::ShortConditional,
+                    (1, 0xE0...0xE3) => Branch::Loop,
+                    _ => return Err(Error::Unmovable(offset)),
+                };
+
+                let next = target + offset + instruction.len;
+                (branch, rel.offset, (next as isize).wrapping_add(rel.read_signed(bytes)) as usize)
+            }
+            None => {
+                let prefixes = bytes.iter().take_while(|&&byte| x86::is_prefix(byte)).count();
+                (Branch::None, prefixes + 1, 0)
+            }
+        };
+
+        let opcode = bytes[opcode_end - 1];
+        ends = match (branch, opcode) {
+            // ret, ret imm16, jmp rel32, jmp rel8.
+            (Branch::None, 0xC3) | (Branch::None, 0xC2) | (Branch::Near, 0xE9) |
+            (Branch::Short, _) => true,
+            // jmp r/m32.
+            (Branch::None, 0xFF) => {
+                let modrm_reg = bytes.get(opcode_end).map_or(0, |&modrm| (modrm >> 3) & 7);
+                modrm_reg == 4 || modrm_reg == 5
+            }
+            _ => false,
+        };
+
+        moved.push(Moved {
+            offset: offset,
+            len: instruction.len,
+            opcode_end: opcode_end,
+            branch: branch,
+            destination: destination,
+            new_offset: 0,
+            new_len: 0,
+        });
+
+        offset += instruction.len;
+
+        if ends && offset < JUMP_LEN {
+            // Fine if the rest of the jump only overwrites the padding after the function.
+            if code.len() < JUMP_LEN || !code[offset..JUMP_LEN].iter().all(|&b| is_padding(b)) {
+                return Err(Error::TooShort(offset));
+            }
+
+            offset = JUMP_LEN;
+        }
+    }
+
+    let stolen = offset;
+
+    // Lay the moved instructions out.
+    let mut new_offset = 0;
+    for instruction in &mut moved {
+        let prefixes = instruction.opcode_end - 1;
+
+        instruction.new_offset = new_offset;
+        instruction.new_len = match instruction.branch {
+            Branch::None | Branch::Near => instruction.len,
+            Branch::Short => prefixes + 5,
+            Branch::ShortConditional => prefixes + 6,
+            Branch::Loop => prefixes + 9,
+        };
+
+        new_offset += instruction.new_len;
+    }
+
+    let mut rv = Vec::with_capacity(new_offset + JUMP_LEN);
+
+    for instruction in &moved {
+        let bytes = &code[instruction.offset..instruction.offset + instruction.len];
+
+        if instruction.branch == Branch::None {
+            rv.extend_from_slice(bytes);
+            continue;
+        }
+
+        // Jumps into the moved code have to go to the moved instructions.
+        let destination = if instruction.destination >= target &&
+                             instruction.destination < target + stolen {
+            let destination_offset = instruction.destination - target;
+
+            match moved.iter().find(|other| other.offset == destination_offset) {
+                Some(other) => trampoline + other.new_offset,
+                None => return Err(Error::Unmovable(instruction.offset)),
+            }
+        } else {
+            instruction.destination
+        };
+
+        let opcode = bytes[instruction.opcode_end - 1];
+        let prefixes = &bytes[..instruction.opcode_end - 1];
+
+        rv.extend_from_slice(prefixes);
+
+        match instruction.branch {
+            Branch::Near => rv.extend_from_slice(&bytes[prefixes.len()..instruction.opcode_end]),
+            Branch::Short => rv.push(0xE9),
+            Branch::ShortConditional => rv.extend_from_slice(&[0x0F, opcode + 0x10]),
+            // loop +2; jmp +5; jmp rel32
+            Branch::Loop => rv.extend_from_slice(&[opcode, 0x02, 0xEB, 0x05, 0xE9]),
+            Branch::None => unreachable!(),
+        }
+
+        let end = trampoline + instruction.new_offset + instruction.new_len;
+        rv.extend_from_slice(&rel32(end, destination));
+    }
+
+    if !ends {
+        let from = trampoline + rv.len();
+        rv.extend_from_slice(&jump(from, target + stolen));
+    }
+
+    Ok(Trampoline {
+        code: rv,
+        stolen: stolen,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: usize = 0x1000_0000;
+    const TRAMPOLINE: usize = 0x2000_0000;
+
+    // Where the rel32 at the offset in the trampoline goes.
+    fn destination(code: &[u8], offset: usize) -> usize {
+        let rel = x86::Operand {
+            offset: offset,
+            size: 4,
+        };
+        (TRAMPOLINE + offset + 4).wrapping_add(rel.read_signed(code) as usize)
+    }
+
+    fn build_at(code: &[u8]) -> Result<Trampoline, Error> {
+        build(code, TARGET, TRAMPOLINE)
+    }
+
+    #[test]
+    fn copy_prologue() {
+        // push ebp; mov ebp, esp; sub esp, 8
+        let code = [0x55, 0x8B, 0xEC, 0x83, 0xEC, 0x08, 0xCC];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 6);
+        assert_eq!(&trampoline.code[..6], &code[..6]);
+        assert_eq!(trampoline.code[6], 0xE9);
+        assert_eq!(trampoline.code.len(), 11);
+        assert_eq!(destination(&trampoline.code, 7), TARGET + 6);
+    }
+
+    #[test]
+    fn steal_the_instruction_across_the_jump() {
+        // push ebp; mov eax, [ebp+8]; mov ecx, [ebp+0xC]
+        let code = [0x55, 0x8B, 0x45, 0x08, 0x8B, 0x4D, 0x0C, 0x90];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 7);
+        assert_eq!(&trampoline.code[..7], &code[..7]);
+        assert_eq!(trampoline.code[7], 0xE9);
+        assert_eq!(destination(&trampoline.code, 8), TARGET + 7);
+    }
+
+    #[test]
+    fn relocate_near_call() {
+        // call +0x100; push ebp
+        let code = [0xE8, 0x00, 0x01, 0x00, 0x00, 0x55];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code.len(), 10);
+        assert_eq!(trampoline.code[0], 0xE8);
+        assert_eq!(destination(&trampoline.code, 1), TARGET + 5 + 0x100);
+        assert_eq!(trampoline.code[5], 0xE9);
+        assert_eq!(destination(&trampoline.code, 6), TARGET + 5);
+    }
+
+    #[test]
+    fn relocate_near_conditional_jump() {
+        // jz -0x10
+        let code = [0x0F, 0x84, 0xF0, 0xFF, 0xFF, 0xFF, 0x55];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 6);
+        assert_eq!(&trampoline.code[..2], &[0x0F, 0x84]);
+        assert_eq!(destination(&trampoline.code, 2), TARGET + 6 - 0x10);
+        assert_eq!(trampoline.code[6], 0xE9);
+        assert_eq!(destination(&trampoline.code, 7), TARGET + 6);
+    }
+
+    #[test]
+    fn relocate_near_jump() {
+        // jmp +0x20, nothing follows it.
+        let code = [0xE9, 0x20, 0x00, 0x00, 0x00, 0x55];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code.len(), 5);
+        assert_eq!(trampoline.code[0], 0xE9);
+        assert_eq!(destination(&trampoline.code, 1), TARGET + 5 + 0x20);
+    }
+
+    #[test]
+    fn relocate_short_jump() {
+        // push ebp; jmp +0x10, then padding.
+        let code = [0x55, 0xEB, 0x10, 0xCC, 0xCC, 0xCC];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code.len(), 6);
+        assert_eq!(&trampoline.code[..2], &[0x55, 0xE9]);
+        assert_eq!(destination(&trampoline.code, 2), TARGET + 3 + 0x10);
+    }
+
+    #[test]
+    fn relocate_short_conditional_jump() {
+        // test eax, eax; jz +5; mov eax, [ebp+8]
+        let code = [0x85, 0xC0, 0x74, 0x05, 0x8B, 0x45, 0x08, 0x90];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 7);
+        assert_eq!(trampoline.code.len(), 2 + 6 + 3 + 5);
+        assert_eq!(&trampoline.code[..4], &[0x85, 0xC0, 0x0F, 0x84]);
+        assert_eq!(destination(&trampoline.code, 4), TARGET + 4 + 5);
+        assert_eq!(&trampoline.code[8..11], &[0x8B, 0x45, 0x08]);
+        assert_eq!(trampoline.code[11], 0xE9);
+        assert_eq!(destination(&trampoline.code, 12), TARGET + 7);
+    }
+
+    #[test]
+    fn relocate_loop() {
+        // xor eax, eax; loop -0x10; nop
+        let code = [0x33, 0xC0, 0xE2, 0xF0, 0x90, 0x90];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code.len(), 2 + 9 + 1 + 5);
+        // loop +2; jmp +5; jmp rel32
+        assert_eq!(&trampoline.code[..7], &[0x33, 0xC0, 0xE2, 0x02, 0xEB, 0x05, 0xE9]);
+        assert_eq!(destination(&trampoline.code, 7), TARGET + 4 - 0x10);
+        assert_eq!(trampoline.code[11], 0x90);
+        assert_eq!(destination(&trampoline.code, 13), TARGET + 5);
+    }
+
+    #[test]
+    fn redirect_jumps_into_the_stolen_code() {
+        // jz +1; nop; nop; nop, the jump goes to the third instruction.
+        let code = [0x74, 0x01, 0x90, 0x90, 0x90, 0x55];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(&trampoline.code[..2], &[0x0F, 0x84]);
+        assert_eq!(destination(&trampoline.code, 2), TRAMPOLINE + 7);
+        assert_eq!(&trampoline.code[6..9], &[0x90, 0x90, 0x90]);
+        assert_eq!(destination(&trampoline.code, 10), TARGET + 5);
+
+        // jz +2 goes into the middle of the mov.
+        let code = [0x74, 0x02, 0x8B, 0x45, 0x08, 0x55];
+        assert_eq!(build_at(&code), Err(Error::Unmovable(0)));
+    }
+
+    #[test]
+    fn stop_after_ret() {
+        // xor eax, eax; ret, then padding.
+        let code = [0x33, 0xC0, 0xC3, 0xCC, 0xCC, 0xCC];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code, vec![0x33, 0xC0, 0xC3]);
+
+        // ret 4
+        let code = [0x33, 0xC0, 0xC2, 0x04, 0x00, 0x55];
+        let trampoline = build_at(&code).unwrap();
+        assert_eq!(trampoline.stolen, 5);
+        assert_eq!(trampoline.code, &code[..5]);
+
+        // jmp [0x12345678]
+        let code = [0xFF, 0x25, 0x78, 0x56, 0x34, 0x12, 0x55];
+        let trampoline = build_at(&code).unwrap();
+        assert_eq!(trampoline.stolen, 6);
+        assert_eq!(trampoline.code, &code[..6]);
+
+        // The next function starts right after the ret.
+        let code = [0x33, 0xC0, 0xC3, 0x55, 0x8B, 0xEC];
+        assert_eq!(build_at(&code), Err(Error::TooShort(3)));
+    }
+
+    #[test]
+    fn return_from_indirect_call() {
+        // call [0x12345678] comes back, so the trampoline jumps back after it.
+        let code = [0xFF, 0x15, 0x78, 0x56, 0x34, 0x12, 0x55];
+        let trampoline = build_at(&code).unwrap();
+
+        assert_eq!(trampoline.stolen, 6);
+        assert_eq!(&trampoline.code[..6], &code[..6]);
+        assert_eq!(trampoline.code[6], 0xE9);
+        assert_eq!(destination(&trampoline.code, 7), TARGET + 6);
+    }
+
+    #[test]
+    fn reject_bad_code() {
+        assert_eq!(build_at(&[0x0F, 0x0A, 0x90, 0x90, 0x90]), Err(Error::Undecodable(0)));
+        assert_eq!(build_at(&[0x55, 0x8B]), Err(Error::Undecodable(1)));
+        // jmp rel16
+        assert_eq!(build_at(&[0x66, 0xE9, 0x00, 0x01, 0x90, 0x90]), Err(Error::Unmovable(0)));
+    }
+}
@@ -1,24 +1,99 @@
 use hooks::*;
+use registry;
+use std::env;
 
 struct Feature {
     name: &'static str,
-    enabled: bool,
+    // Whether everything the feature needs was found.
+    available: bool,
+    // Whether the user wants the feature.
+    wanted: bool,
+    // The registry names of the hookables the feature needs.
+    hookables: &'static [&'static str],
+    // The hooked functions that are only needed by the feature, by hookable. They're turned off
+    // with it.
+    detours: &'static [(&'static str, &'static str)],
 }
 
 define_features! {
-    (autopause, AUTOPAUSE, "autopause"),
-    (console_commands, CONSOLE_COMMANDS, "console commands"),
-    (autojump, AUTOJUMP, "autojump")
+    (autopause, AUTOPAUSE, "autopause", ["engine"], [("engine", "Host_UnPause_f")]),
+    (console_commands, CONSOLE_COMMANDS, "console commands", ["engine"], []),
+    (autojump, AUTOJUMP, "autojump", ["server"],
+        [("server", "CHL1GameMovement__CheckJumpButton"),
+         ("server", "CGameMovement__FinishGravity")]),
+    (speedometer, SPEEDOMETER, "speedometer", ["client"],
+        [("client", "CGameMovement__FinishGravity"), ("client", "CHudCrosshair__Paint")])
+}
+
+// Turns off the features in Y_DISABLED_FEATURES, a comma-separated list of keys like
+// "autojump,speedometer".
+pub fn init() {
+    if let Ok(disabled) = env::var("Y_DISABLED_FEATURES") {
+        for key in disabled.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+            match unsafe { feature_mut(key) } {
+                Some(feature) => feature.wanted = false,
+                None => warn!(target: "", "Unknown feature in Y_DISABLED_FEATURES: {}", key),
+            }
+        }
+    }
 }
 
 fn log() {
     info!(target: "", "Features:");
 
     for feature in FEATURES {
-        if feature.enabled {
-            info!(target: "", "✔ {}", feature.name);
-        } else {
+        if !feature.available {
             warn!(target: "", "❌ {}", feature.name);
+        } else if !feature.wanted {
+            warn!(target: "", "❌ {} (turned off)", feature.name);
+        } else {
+            info!(target: "", "✔ {}", feature.name);
+        }
+    }
+}
+
+// The features that need the hookable, and whether they're enabled.
+pub fn depending_on(hookable: &str) -> Vec<(&'static str, bool)> {
+    FEATURES.iter()
+            .filter(|feature| feature.hookables.iter().any(|&name| name == hookable))
+            .map(|feature| (feature.name, feature.available && feature.wanted))
+            .collect()
+}
+
+pub fn set_wanted(key: &str, wanted: bool) -> Result<(), String> {
+    match unsafe { feature_mut(key) } {
+        Some(feature) => feature.wanted = wanted,
+        None => return Err(format!("Unknown feature: {}", key)),
+    }
+
+    refresh();
+
+    Ok(())
+}
+
+pub fn toggle(key: &str) -> Result<(), String> {
+    let wanted = match unsafe { feature_mut(key) } {
+        Some(feature) => feature.wanted,
+        None => return Err(format!("Unknown feature: {}", key)),
+    };
+
+    set_wanted(key, !wanted)
+}
+
+// Turns the detours of the available features on or off to match whether they're wanted. Hooking
+// always turns the detours on, so this has to run after every hook.
+fn sync_detours() {
+    for feature in FEATURES.iter().filter(|feature| feature.available) {
+        for &(hookable, function) in feature.detours {
+            let entry = match registry::get(hookable) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            let result = entry.hookable.write().unwrap().set_hook_enabled(function, feature.wanted);
+            if let Err(err) = result {
+                warn!(target: "", "{}", err);
+            }
         }
     }
 }
@@ -27,20 +102,27 @@ pub fn refresh() {
     unsafe {
         let engine = engine::POINTERS.read().unwrap();
         let server = server::POINTERS.read().unwrap();
+        let client = client::POINTERS.read().unwrap();
 
-        AUTOPAUSE.enabled =
+        AUTOPAUSE.available =
             !engine.Cbuf_AddText.is_default()
             && !engine.Host_Spawn_f.is_default()
             && !engine.Host_UnPause_f.is_default();
 
-        CONSOLE_COMMANDS.enabled =
+        CONSOLE_COMMANDS.available =
             engine.icvar.is_some()
             && engine.concommand_vtable.is_some();
 
-        AUTOJUMP.enabled =
+        AUTOJUMP.available =
             !server.CHL1GameMovement__CheckJumpButton.is_default()
-            && !server.CGameMovement__FinishGravity.is_default()
+            && !server.CGameMovement__FinishGravity.is_default();
+
+        SPEEDOMETER.available =
+            !client.CGameMovement__FinishGravity.is_default()
+            && !client.CHudCrosshair__Paint.is_default()
+            && client.surface.is_some();
     }
 
+    sync_detours();
     log();
 }
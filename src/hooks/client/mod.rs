@@ -0,0 +1,228 @@
+use features;
+use hookable::*;
+use interfaces;
+use libc::*;
+use matcher::ModuleMatcher;
+use moduleinfo::{AddressResult, ModuleInfo};
+use moduleinfo::windows;
+use registry;
+use signature::Batch;
+use status::{self, FunctionStatus};
+use std::sync::RwLock;
+use utils;
+use winapi;
+
+pub mod signatures;
+use self::signatures::*;
+pub mod surface;
+use self::surface::*;
+
+// Where the speedometer is drawn, relative to the crosshair panel which covers the screen.
+const SPEEDOMETER_X: c_int = 16;
+const SPEEDOMETER_Y: c_int = 16;
+
+lazy_static! {
+    pub static ref MODULE: RwLock<ClientModule> = RwLock::new(ClientModule::default());
+    static ref DATA: RwLock<Data> = RwLock::new(Data::default());
+}
+
+#[derive(Default)]
+struct Data {
+    // The horizontal speed of the local player as of the last predicted movement.
+    speed: f32,
+    font: Option<HFont>,
+}
+
+#[derive(Default)]
+pub struct ClientModule {
+    module_info: Option<ModuleInfo>,
+    current_name_index: Option<usize>,
+    functions: Vec<FunctionStatus>,
+}
+
+unsafe impl Send for Client {}
+unsafe impl Sync for Client {}
+
+pub fn register() {
+    registry::register("client", &*MODULE, &[]);
+}
+
+hook_struct! {
+    #[derive(Default)]
+    pub struct Client {
+        pub off_mv: isize,
+        pub off_velocity: isize,
+        pub surface: Option<*mut ISurface>,
+    }
+
+    impl Client {
+        pub extern "fastcall" fn CGameMovement__FinishGravity(this: *mut c_void) {
+            Client::CGameMovement__FinishGravity(this);
+
+            if features::speedometer() {
+                let pointers = POINTERS.read().unwrap();
+
+                let velocity = unsafe {
+                    let mv = *((this as *mut u8).offset(pointers.off_mv) as *mut *mut u8);
+                    *(mv.offset(pointers.off_velocity) as *const [f32; 3])
+                };
+
+                DATA.write().unwrap().speed = velocity[0].hypot(velocity[1]);
+            }
+        }
+
+        pub extern "fastcall" fn CHudCrosshair__Paint(this: *mut c_void) {
+            Client::CHudCrosshair__Paint(this);
+
+            if features::speedometer() {
+                Client::draw_speedometer();
+            }
+        }
+    }
+}
+
+impl Hookable for ClientModule {
+    fn module_info(&self) -> Option<&ModuleInfo> {
+        self.module_info.as_ref()
+    }
+
+    fn functions(&self) -> &[FunctionStatus] {
+        &self.functions
+    }
+
+    fn hook(&mut self, module_info: &ModuleInfo) {
+        self.module_info = Some(module_info.clone());
+
+        self.current_name_index = self.compute_current_name_index(&windows::WINDOWS, module_info);
+
+        debug!(target: "client", "Base: {:p}; size = {}", module_info.base, module_info.size);
+
+        let batch = Batch::new(module_info, SIGNATURES);
+        let addr_CGameMovement__FinishGravity = CGameMovement__FinishGravity.find_in(&batch);
+        let addr_CHudCrosshair__Paint = CHudCrosshair__Paint.find_in(&batch);
+        // The launcher loads the surface long before the client.
+        let surface = ModuleInfo::get("vguimatsurface.dll").and_then(|module_info| {
+            match interfaces::create::<ISurface>(&module_info, VGUI_SURFACE_INTERFACE_VERSION) {
+                Ok(surface) => Some(surface),
+                Err(err) => {
+                    warn!(target: "client", "Error creating ISurface: {}", err);
+                    None
+                }
+            }
+        });
+
+        self.functions = print_addrs!("client",
+            (addr_CGameMovement__FinishGravity, CGameMovement__FinishGravity.name),
+            (addr_CHudCrosshair__Paint, CHudCrosshair__Paint.name),
+            (surface.map(|surface| surface as winapi::LPVOID), "ISurface")
+        );
+
+        let mut pointers = POINTERS.write().unwrap();
+
+        // The detour needs the offsets of the matched variant, so an address found any other way
+        // can't be hooked.
+        let mut finishgravity = None;
+
+        if let Ok(found) = addr_CGameMovement__FinishGravity {
+            match found.variant() {
+                Some(variant) => {
+                    pointers.off_mv = variant.offsets.mv;
+                    pointers.off_velocity = variant.offsets.velocity;
+                    finishgravity = Some(found.address);
+                }
+                None => {
+                    let err = "No offsets for this build, not hooking it".to_owned();
+                    warn!(target: "client", "{}: {}", CGameMovement__FinishGravity.name, err);
+                    status::record_hooks(&mut self.functions, vec![(found.address, Err(err))]);
+                }
+            }
+        }
+
+        pointers.surface = surface;
+
+        let hooked = hook!("client", Client, pointers,
+            (finishgravity, CGameMovement__FinishGravity),
+            (addr_CHudCrosshair__Paint.address(), CHudCrosshair__Paint)
+        );
+
+        status::record_hooks(&mut self.functions, hooked);
+    }
+
+    fn unhook(&mut self) {
+        let mut pointers = POINTERS.write().unwrap();
+
+        unhook!("client", pointers,
+            CGameMovement__FinishGravity,
+            CHudCrosshair__Paint
+        );
+
+        *DATA.write().unwrap() = Data::default();
+        *pointers = Client::default();
+        *self = Self::default();
+    }
+
+    fn set_hook_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let target = try!(Client::set_hook_enabled(name, enabled));
+        status::record_enabled(&mut self.functions, target, enabled);
+
+        Ok(())
+    }
+}
+
+impl HookableOrderedNameFilter for ClientModule {
+    fn get_current_name_index(&self) -> Option<usize> {
+        self.current_name_index
+    }
+
+    fn get_matchers(&self) -> &[ModuleMatcher] {
+        // Prefer HL:S when the modules of several games are loaded.
+        const MATCHERS: &'static [ModuleMatcher] = &[
+            ModuleMatcher::PathSuffix("hl1/bin/client.dll"),
+            ModuleMatcher::Name("client.dll"),
+        ];
+        MATCHERS
+    }
+}
+
+impl Client {
+    // Turns the detour of a function like "CHudCrosshair__Paint" on or off.
+    // Returns the hooked address.
+    pub fn set_hook_enabled(name: &str, enabled: bool) -> Result<winapi::LPVOID, String> {
+        let pointers = POINTERS.read().unwrap();
+        set_hook_enabled!(pointers, name, enabled,
+            CGameMovement__FinishGravity,
+            CHudCrosshair__Paint
+        )
+    }
+
+    fn draw_speedometer() {
+        let surface = match POINTERS.read().unwrap().surface {
+            Some(surface) => unsafe { &mut *surface },
+            None => return,
+        };
+
+        let mut data = DATA.write().unwrap();
+
+        let font = match data.font {
+            Some(font) => font,
+            None => {
+                let font = surface.create_font();
+                surface.set_font_glyph_set(font,
+                                           cstr!(b"Tahoma\0"),
+                                           24,
+                                           500,
+                                           FONTFLAG_ANTIALIAS | FONTFLAG_OUTLINE);
+                data.font = Some(font);
+                font
+            }
+        };
+
+        let text = utils::utf16(&format!("{:.0} u/s", data.speed));
+
+        surface.draw_set_text_font(font);
+        surface.draw_set_text_color(255, 255, 255, 255);
+        surface.draw_set_text_pos(SPEEDOMETER_X, SPEEDOMETER_Y);
+        // Without the null terminator.
+        surface.draw_print_text(&text[..text.len() - 1]);
+    }
+}
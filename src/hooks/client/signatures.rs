@@ -0,0 +1,44 @@
+use signature::{AnySignature, Signature, Variant};
+
+// The same code as in server.dll, except for the offsets of the player fields.
+pattern!(CGameMovement__FinishGravity_OOE
+    0x8B 0x51 0x08 0xD9 0x82 ?? ?? ?? ?? 0xD8 0x1D ?? ?? ?? ?? 0xDF 0xE0 0xF6 0xC4 0x44 0x7A 0x4D 0xD9 0x82 ?? ?? ?? ?? 0xD8 0x1D
+);
+
+pattern!(CHudCrosshair__Paint_OOE
+    0x83 0xEC 0x18 0x56 0x8B 0xF1 0x83 0xBE ?? ?? ?? ?? 0x00 0x0F 0x84 ?? ?? ?? ?? 0xE8 ?? ?? ?? ?? 0x84 0xC0 0x0F 0x84
+);
+
+pub struct GameMovementOffsets {
+    pub mv: isize,
+    // CMoveData::m_vecVelocity.
+    pub velocity: isize,
+}
+
+pub static CGameMovement__FinishGravity: Signature<GameMovementOffsets> = Signature {
+    name: "CGameMovement::FinishGravity()",
+    variants: &[
+        Variant {
+            name: "HL:S OOE",
+            pattern: CGameMovement__FinishGravity_OOE,
+            offsets: GameMovementOffsets {
+                mv: 4,
+                velocity: 64,
+            },
+        },
+    ],
+    string_ref: None,
+};
+
+pub static CHudCrosshair__Paint: Signature = Signature {
+    name: "CHudCrosshair::Paint()",
+    variants: &[
+        Variant { name: "HL:S OOE", pattern: CHudCrosshair__Paint_OOE, offsets: () },
+    ],
+    string_ref: None,
+};
+
+pub static SIGNATURES: &'static [&'static AnySignature] = &[
+    &CGameMovement__FinishGravity,
+    &CHudCrosshair__Paint,
+];
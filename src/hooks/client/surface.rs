@@ -0,0 +1,89 @@
+use libc::*;
+use std::mem;
+
+pub type HFont = c_ulong;
+
+pub const FONTFLAG_ANTIALIAS: c_int = 0x010;
+pub const FONTFLAG_OUTLINE: c_int = 0x200;
+
+// Indices into the vtable of VGUI_Surface030.
+const DrawSetTextFont: isize = 17;
+const DrawSetTextColor: isize = 19;
+const DrawSetTextPos: isize = 20;
+const DrawPrintText: isize = 22;
+const CreateFont: isize = 66;
+const SetFontGlyphSet: isize = 67;
+
+#[repr(C)]
+pub struct ISurface {
+    vtable: *const usize,
+}
+
+impl ISurface {
+    unsafe fn function<F>(&self, index: isize) -> F {
+        mem::transmute_copy(&*self.vtable.offset(index))
+    }
+
+    pub fn create_font(&mut self) -> HFont {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface, i32) -> HFont = self.function(CreateFont);
+            f(self, 0)
+        }
+    }
+
+    pub fn set_font_glyph_set(&mut self,
+                              font: HFont,
+                              name: *const c_char,
+                              tall: c_int,
+                              weight: c_int,
+                              flags: c_int)
+                              -> bool {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface,
+                                        i32,
+                                        HFont,
+                                        *const c_char,
+                                        c_int,
+                                        c_int,
+                                        c_int,
+                                        c_int,
+                                        c_int)
+                                        -> bool = self.function(SetFontGlyphSet);
+            f(self, 0, font, name, tall, weight, 0, 0, flags)
+        }
+    }
+
+    pub fn draw_set_text_font(&mut self, font: HFont) {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface, i32, HFont) = self.function(DrawSetTextFont);
+            f(self, 0, font)
+        }
+    }
+
+    pub fn draw_set_text_color(&mut self, r: c_int, g: c_int, b: c_int, a: c_int) {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface, i32, c_int, c_int, c_int, c_int) =
+                self.function(DrawSetTextColor);
+            f(self, 0, r, g, b, a)
+        }
+    }
+
+    pub fn draw_set_text_pos(&mut self, x: c_int, y: c_int) {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface, i32, c_int, c_int) =
+                self.function(DrawSetTextPos);
+            f(self, 0, x, y)
+        }
+    }
+
+    // The text doesn't need to be null-terminated.
+    pub fn draw_print_text(&mut self, text: &[u16]) {
+        unsafe {
+            let f: extern "fastcall" fn(*mut ISurface, i32, *const u16, c_int, c_int) =
+                self.function(DrawPrintText);
+            f(self, 0, text.as_ptr(), text.len() as c_int, 0)
+        }
+    }
+}
+
+pub const VGUI_SURFACE_INTERFACE_VERSION: &'static str = "VGUI_Surface030";
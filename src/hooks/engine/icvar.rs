@@ -1,4 +1,3 @@
-use libc;
 use libc::*;
 use std::ops;
 
@@ -77,4 +76,4 @@ impl ICVar {
     }
 }
 
-pub const VENGINE_CVAR_INTERFACE_VERSION: *const c_char = cstr!(b"VEngineCvar001\0");
+pub const VENGINE_CVAR_INTERFACE_VERSION: &'static str = "VEngineCvar001";
@@ -1,15 +1,24 @@
 use features;
 use function::Function;
 use hookable::*;
+use interfaces;
 use libc;
 use libc::*;
-use moduleinfo::ModuleInfo;
+use matcher::ModuleMatcher;
+use moduleinfo::{AddressResult, ModuleInfo};
+use moduleinfo::windows;
+use registry;
+use signature::Batch;
+use status::{self, FunctionStatus};
 use std::{mem, ptr};
+use std::ffi::CString;
 use std::sync::RwLock;
 use winapi;
 
 pub mod icvar;
 use self::icvar::*;
+pub mod signatures;
+use self::signatures::*;
 
 lazy_static! {
     pub static ref MODULE: RwLock<EngineModule> = RwLock::new(EngineModule::default());
@@ -25,11 +34,16 @@ struct Data {
 pub struct EngineModule {
     module_info: Option<ModuleInfo>,
     current_name_index: Option<usize>,
+    functions: Vec<FunctionStatus>,
 }
 
 unsafe impl Send for Engine {}
 unsafe impl Sync for Engine {}
 
+pub fn register() {
+    registry::register("engine", &*MODULE, &[]);
+}
+
 hook_struct! {
     #[derive(Default)]
     pub struct Engine {
@@ -39,6 +53,7 @@ hook_struct! {
         pub CreateInterface: Function<extern "C" fn(name: *const c_char,
                                                     return_code: *mut c_int) -> *mut c_void>,
         pub icvar: Option<*mut ICVar>,
+        pub paused: Option<*mut bool>,
         pub concommand_vtable: Option<*mut c_void>,
     }
 
@@ -56,16 +71,17 @@ hook_struct! {
         pub extern "C" fn Host_UnPause_f() {
             trace!(target: "engine", "Entering Host_UnPause_f()");
 
-            if features::autopause() {
-                let mut engine = DATA.write().unwrap();
-                if engine.next_unpause_is_bad {
-                    engine.next_unpause_is_bad = false;
-                    Engine::Cbuf_AddText(cstr!(b"setpause\n\0"));
-                }
-            }
+            let unpause_is_bad = features::autopause() &&
+                                 mem::replace(&mut DATA.write().unwrap().next_unpause_is_bad,
+                                              false);
 
             Engine::Host_UnPause_f();
 
+            // If the game didn't get unpaused there's nothing to undo.
+            if unpause_is_bad && !Engine::is_paused() {
+                Engine::Cbuf_AddText(cstr!(b"setpause\n\0"));
+            }
+
             trace!(target: "engine", "Leaving  Host_UnPause_f()");
         }
     }
@@ -75,68 +91,105 @@ con_command!(hello, b"hello\0" {
     Engine::Cbuf_AddText(cstr!(b"echo hello\n\0"));
 });
 
-pattern!(Cbuf_AddText
-    0x8B 0x54 0x24 0x04 0x83 0xC9 0xFF 0x57 0x33 0xC0 0x8B 0xFA 0xF2 0xAE 0x8B 0x3D ?? ?? ?? ?? 0xA1 ?? ?? ?? ?? 0xF7 0xD1 0x49 0x03 0xCF 0x3B 0xC8
-);
+con_command!(y_interfaces, b"y_interfaces\0" {
+    for (module, interfaces) in interfaces::list_all() {
+        info!(target: "interfaces", "{}:", module);
 
-pattern!(Host_Spawn_f
-    0xA1 ?? ?? ?? ?? 0x53 0xBB 0x01 0x00 0x00 0x00 0x3B 0xC3 0x56 0x75 0x11 0x68 ?? ?? ?? ?? 0xFF 0x15 ?? ?? ?? ?? 0x83 0xC4 0x04 0x5E 0x5B
-);
+        for interface in interfaces {
+            info!(target: "interfaces",
+                  "    {:p} - {}",
+                  interface.create_fn as *const c_void,
+                  interface.name);
+        }
+    }
+});
 
-pattern!(Host_UnPause_f
-    0xA0 ?? ?? ?? ?? 0x84 0xC0 0x74 0x59 0x8B 0x0D ?? ?? ?? ?? 0xB8 0x01 0x00 0x00 0x00 0x3B 0xC8 0x75 0x0A 0x50 0xE8
-);
+con_command!(y_status, b"y_status\0" {
+    status::log();
+});
 
-pattern!(ConCommand__ConCommand
-    0x8B 0x44 0x24 0x08 0x33 0xD2 0x56 0x8B 0xF1 0x89 0x46 0x18 0x8B 0x44 0x24 0x18 0x3B 0xC2 0x88 0x56 0x08 0x89 0x56 0x0C 0x89 0x56 0x10 0x89 0x56 0x14 0x89 0x56 0x04 0xC7 0x06
-);
+con_command!(y_toggle_autopause, b"y_toggle_autopause\0" {
+    Engine::toggle_feature("autopause");
+});
+
+con_command!(y_toggle_autojump, b"y_toggle_autojump\0" {
+    Engine::toggle_feature("autojump");
+});
+
+con_command!(y_toggle_speedometer, b"y_toggle_speedometer\0" {
+    Engine::toggle_feature("speedometer");
+});
 
 impl Hookable for EngineModule {
     fn module_info(&self) -> Option<&ModuleInfo> {
         self.module_info.as_ref()
     }
 
+    fn functions(&self) -> &[FunctionStatus] {
+        &self.functions
+    }
+
     fn hook(&mut self, module_info: &ModuleInfo) {
         self.module_info = Some(module_info.clone());
 
-        self.current_name_index = self.compute_current_name_index(module_info);
+        self.current_name_index = self.compute_current_name_index(&windows::WINDOWS, module_info);
 
         debug!(target: "engine", "Base: {:p}; size = {}", module_info.base, module_info.size);
 
-        let addr_Cbuf_AddText = module_info.find(Cbuf_AddText);
-        let addr_Host_Spawn_f = module_info.find(Host_Spawn_f);
-        let addr_Host_UnPause_f = module_info.find(Host_UnPause_f);
-        let addr_ConCommand__ConCommand = module_info.find(ConCommand__ConCommand);
-        let addr_CreateInterface = module_info.get_function(cstr!(b"CreateInterface\0"));
-
-        print_addrs!("engine",
-            (addr_Cbuf_AddText, "Cbuf_AddText()"),
-            (addr_Host_Spawn_f, "Host_Spawn_f()"),
-            (addr_Host_UnPause_f, "Host_UnPause_f()"),
-            (addr_ConCommand__ConCommand, "ConCommand::ConCommand()"),
-            (addr_CreateInterface, "CreateInterface()")
+        let batch = Batch::new(module_info, SIGNATURES);
+        let addr_Cbuf_AddText = Cbuf_AddText.find_in(&batch);
+        let addr_Host_Spawn_f = Host_Spawn_f.find_in(&batch);
+        let addr_Host_UnPause_f = Host_UnPause_f.find_in(&batch);
+        let addr_ConCommand__ConCommand = ConCommand__ConCommand.find_in(&batch);
+        let addr_CreateInterface = module_info.get_function("CreateInterface");
+        // RTTI finds the vtable in any build, the constructor pattern is only a fallback. The
+        // offsets are only known when the constructor was found with a pattern.
+        let addr_ConCommand_vtable = module_info.find_vtable("ConCommand").or_else(|| {
+            addr_ConCommand__ConCommand.ok().and_then(|found| {
+                found.variant().map(|variant| unsafe {
+                    *((found.address as *mut u8).offset(variant.offsets.vtable)
+                        as *const winapi::LPVOID)
+                })
+            })
+        });
+        let addr_paused = addr_Host_UnPause_f.ok()
+                                             .and_then(|found| found.imm32("paused"))
+                                             .map(|addr| addr as winapi::LPVOID);
+
+        self.functions = print_addrs!("engine",
+            (addr_Cbuf_AddText, Cbuf_AddText.name),
+            (addr_Host_Spawn_f, Host_Spawn_f.name),
+            (addr_Host_UnPause_f, Host_UnPause_f.name),
+            (addr_ConCommand__ConCommand, ConCommand__ConCommand.name),
+            (addr_CreateInterface, "CreateInterface()"),
+            (addr_ConCommand_vtable, "ConCommand::`vftable'"),
+            (addr_paused, "sv.paused")
         );
 
         let mut pointers = POINTERS.write().unwrap();
 
-        if let Some(addr) = addr_Cbuf_AddText {
+        if let Some(addr) = addr_Cbuf_AddText.address() {
             pointers.Cbuf_AddText.ptr = unsafe { mem::transmute(addr) };
         }
 
+        if let Some(addr) = addr_paused {
+            pointers.paused = Some(addr as *mut bool);
+        }
+
         if let Some(addr) = addr_CreateInterface {
             pointers.CreateInterface.ptr = unsafe { mem::transmute(addr) };
         }
 
-        if let Some(addr) = addr_ConCommand__ConCommand {
-            pointers.concommand_vtable = Some(unsafe {
-                *((addr as *mut u8).offset(35) as *const *mut c_void)
-            });
+        if let Some(addr) = addr_ConCommand_vtable {
+            pointers.concommand_vtable = Some(addr as *mut c_void);
         }
 
-        hook!("engine", Engine, pointers,
-            (addr_Host_Spawn_f, Host_Spawn_f),
-            (addr_Host_UnPause_f, Host_UnPause_f)
+        let hooked = hook!("engine", Engine, pointers,
+            (addr_Host_Spawn_f.address(), Host_Spawn_f),
+            (addr_Host_UnPause_f.address(), Host_UnPause_f)
         );
+
+        status::record_hooks(&mut self.functions, hooked);
     }
 
     fn unhook(&mut self) {
@@ -151,6 +204,13 @@ impl Hookable for EngineModule {
         *pointers = Engine::default();
         *self = Self::default();
     }
+
+    fn set_hook_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let target = try!(Engine::set_hook_enabled(name, enabled));
+        status::record_enabled(&mut self.functions, target, enabled);
+
+        Ok(())
+    }
 }
 
 impl HookableOrderedNameFilter for EngineModule {
@@ -158,9 +218,9 @@ impl HookableOrderedNameFilter for EngineModule {
         self.current_name_index
     }
 
-    fn get_names(&self) -> &[&'static str] {
-        const NAMES: &'static [&'static str] = &[ "engine.dll" ];
-        NAMES
+    fn get_matchers(&self) -> &[ModuleMatcher] {
+        const MATCHERS: &'static [ModuleMatcher] = &[ ModuleMatcher::Name("engine.dll") ];
+        MATCHERS
     }
 }
 
@@ -173,21 +233,67 @@ impl Engine {
 
         POINTERS.write().unwrap().initialized = true;
 
-        let icvar = Engine::create_interface(VENGINE_CVAR_INTERFACE_VERSION)
-            .map(|p| p as *mut ICVar);
+        let icvar = match Engine::create_interface::<ICVar>(VENGINE_CVAR_INTERFACE_VERSION) {
+            Ok(icvar) => Some(icvar),
+            Err(err) => {
+                warn!(target: "engine", "Error creating ICVar: {}", err);
+                None
+            }
+        };
         POINTERS.write().unwrap().icvar = icvar;
 
         features::refresh();
 
         if features::console_commands() {
-            unsafe { Engine::register_concmd(&mut hello); }
+            unsafe {
+                Engine::register_concmd(&mut hello);
+                Engine::register_concmd(&mut y_interfaces);
+                Engine::register_concmd(&mut y_status);
+                Engine::register_concmd(&mut y_toggle_autopause);
+                Engine::register_concmd(&mut y_toggle_autojump);
+                Engine::register_concmd(&mut y_toggle_speedometer);
+            }
         }
     }
 
-    fn create_interface(name: *const c_char) -> Option<*mut c_void> {
-        match Engine::CreateInterface(name, ptr::null_mut()) {
-            p if p == ptr::null_mut() => None,
-            p => Some(p),
+    // Creates an interface with the engine's CreateInterface(). The interface list is only walked
+    // to say which versions there are when it fails.
+    fn create_interface<T>(name: &str) -> Result<*mut T, interfaces::Error> {
+        if POINTERS.read().unwrap().CreateInterface.is_default() {
+            return Err(interfaces::Error::NoCreateInterface);
+        }
+
+        let c_name = CString::new(name).unwrap();
+        let interface = Engine::CreateInterface(c_name.as_ptr(), ptr::null_mut());
+
+        if !interface.is_null() {
+            return Ok(interface as *mut T);
+        }
+
+        Err(match MODULE.read().unwrap().module_info {
+            Some(ref module_info) => interfaces::not_found(module_info, name),
+            None => interfaces::Error::NoRegistry,
+        })
+    }
+
+    // Turns the detour of a function like "Host_UnPause_f" on or off.
+    // Returns the hooked address.
+    pub fn set_hook_enabled(name: &str, enabled: bool) -> Result<winapi::LPVOID, String> {
+        let pointers = POINTERS.read().unwrap();
+        set_hook_enabled!(pointers, name, enabled,
+            Host_Spawn_f,
+            Host_UnPause_f
+        )
+    }
+
+    // Whether sv.paused is set. Assumes it's not when the flag wasn't found.
+    fn is_paused() -> bool {
+        POINTERS.read().unwrap().paused.map_or(false, |paused| unsafe { *paused })
+    }
+
+    fn toggle_feature(name: &str) {
+        if let Err(err) = features::toggle(name) {
+            warn!(target: "engine", "{}", err);
         }
     }
 
@@ -0,0 +1,58 @@
+use signature::{AnySignature, Signature, Variant};
+
+pattern!(Cbuf_AddText_OOE
+    0x8B 0x54 0x24 0x04 0x83 0xC9 0xFF 0x57 0x33 0xC0 0x8B 0xFA 0xF2 0xAE 0x8B 0x3D ?? ?? ?? ?? 0xA1 ?? ?? ?? ?? 0xF7 0xD1 0x49 0x03 0xCF 0x3B 0xC8
+);
+
+pattern!(Host_Spawn_f_OOE
+    0xA1 ?? ?? ?? ?? 0x53 0xBB 0x01 0x00 0x00 0x00 0x3B 0xC3 0x56 0x75 0x11 0x68 ?? ?? ?? ?? 0xFF 0x15 ?? ?? ?? ?? 0x83 0xC4 0x04 0x5E 0x5B
+);
+
+pattern!(Host_UnPause_f_OOE
+    0xA0 @paused ?? ?? ?? ?? 0x84 0xC0 0x74 0x59 0x8B 0x0D ?? ?? ?? ?? 0xB8 0x01 0x00 0x00 0x00 0x3B 0xC8 0x75 0x0A 0x50 0xE8
+);
+
+pattern!(ConCommand__ConCommand_OOE
+    0x8B 0x44 0x24 0x08 0x33 0xD2 0x56 0x8B 0xF1 0x89 0x46 0x18 0x8B 0x44 0x24 0x18 0x3B 0xC2 0x88 0x56 0x08 0x89 0x56 0x0C 0x89 0x56 0x10 0x89 0x56 0x14 0x89 0x56 0x04 0xC7 0x06
+);
+
+pub struct ConCommandOffsets {
+    pub vtable: isize,
+}
+
+pub static Cbuf_AddText: Signature = Signature {
+    name: "Cbuf_AddText()",
+    variants: &[Variant { name: "HL:S OOE", pattern: Cbuf_AddText_OOE, offsets: () }],
+    string_ref: Some("Cbuf_AddText: overflow\n"),
+};
+
+pub static Host_Spawn_f: Signature = Signature {
+    name: "Host_Spawn_f()",
+    variants: &[Variant { name: "HL:S OOE", pattern: Host_Spawn_f_OOE, offsets: () }],
+    string_ref: Some("spawn is not valid from the console\n"),
+};
+
+pub static Host_UnPause_f: Signature = Signature {
+    name: "Host_UnPause_f()",
+    variants: &[Variant { name: "HL:S OOE", pattern: Host_UnPause_f_OOE, offsets: () }],
+    string_ref: None,
+};
+
+pub static ConCommand__ConCommand: Signature<ConCommandOffsets> = Signature {
+    name: "ConCommand::ConCommand()",
+    variants: &[
+        Variant {
+            name: "HL:S OOE",
+            pattern: ConCommand__ConCommand_OOE,
+            offsets: ConCommandOffsets { vtable: 35 },
+        },
+    ],
+    string_ref: None,
+};
+
+pub static SIGNATURES: &'static [&'static AnySignature] = &[
+    &Cbuf_AddText,
+    &Host_Spawn_f,
+    &Host_UnPause_f,
+    &ConCommand__ConCommand,
+];
@@ -1,27 +1,51 @@
 use features;
 use function::Function;
-use hookable::Hookable;
-use hooks::*;
-use libc;
+use iat::{self, IatHook};
+use matcher::ModuleMatcher;
 use moduleinfo::ModuleInfo;
-use std::ffi;
+use moduleinfo::provider::ModuleProvider;
+use moduleinfo::windows;
+use registry;
+use status;
+use std::{env, ffi, mem};
 use std::sync::RwLock;
-use utils;
 use widestring::WideCStr;
+use winapi;
 use winapi::*;
 
 lazy_static! {
     pub static ref MODULE: RwLock<Kernel32Module> = RwLock::new(Kernel32Module::default());
+}
 
-    static ref HOOKS: [&'static RwLock<Hookable>; 2] = [
-        server::MODULE.deref(),
-        engine::MODULE.deref()
-    ];
+// The modules whose imports are patched with Method::Iat. Between them, they load every module
+// that is hooked.
+const IAT_MODULES: &'static [ModuleMatcher] = &[
+    ModuleMatcher::Name("hl2.exe"),
+    ModuleMatcher::Name("launcher.dll"),
+    ModuleMatcher::Name("engine.dll"),
+];
+
+// How LoadLibrary and FreeLibrary are hooked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    // Patching the functions in kernel32.
+    Inline,
+    // Patching the imports of IAT_MODULES, which leaves kernel32 alone for overlays and other
+    // tools that patch it.
+    Iat,
+}
+
+impl Default for Method {
+    fn default() -> Self {
+        Method::Inline
+    }
 }
 
 #[derive(Default)]
 pub struct Kernel32Module {
     module_info: Option<ModuleInfo>,
+    method: Method,
+    iat_hooks: Vec<IatHook>,
 }
 
 hook_struct! {
@@ -81,6 +105,8 @@ hook_struct! {
 
             trace!(target: "kernel32", "FreeLibrary({:p}) -> {}", hModule, rv);
 
+            MODULE.write().unwrap().forget_unloaded_imports(&windows::WINDOWS, hModule);
+
             rv
         }
     }
@@ -92,11 +118,11 @@ impl Kernel32Module {
 
         debug!(target: "kernel32", "Base: {:p}; size = {}", module_info.base, module_info.size);
 
-        let addr_LoadLibraryA = module_info.get_function(cstr!(b"LoadLibraryA\0"));
-        let addr_LoadLibraryW = module_info.get_function(cstr!(b"LoadLibraryW\0"));
-        let addr_LoadLibraryExA = module_info.get_function(cstr!(b"LoadLibraryExA\0"));
-        let addr_LoadLibraryExW = module_info.get_function(cstr!(b"LoadLibraryExW\0"));
-        let addr_FreeLibrary = module_info.get_function(cstr!(b"FreeLibrary\0"));
+        let addr_LoadLibraryA = module_info.get_function("LoadLibraryA");
+        let addr_LoadLibraryW = module_info.get_function("LoadLibraryW");
+        let addr_LoadLibraryExA = module_info.get_function("LoadLibraryExA");
+        let addr_LoadLibraryExW = module_info.get_function("LoadLibraryExW");
+        let addr_FreeLibrary = module_info.get_function("FreeLibrary");
 
         print_addrs!("kernel32",
             (addr_LoadLibraryA, "LoadLibraryA"),
@@ -106,76 +132,203 @@ impl Kernel32Module {
             (addr_FreeLibrary, "FreeLibrary")
         );
 
+        let found = [addr_LoadLibraryA,
+                     addr_LoadLibraryW,
+                     addr_LoadLibraryExA,
+                     addr_LoadLibraryExW,
+                     addr_FreeLibrary];
+        self.method = Kernel32Module::choose_method(found.iter().filter_map(|&addr| addr));
+
+        info!(target: "kernel32", "Hooking method: {:?}", self.method);
+
         let mut pointers = POINTERS.write().unwrap();
 
-        hook!("kernel32", Kernel32, pointers,
-            (addr_LoadLibraryA, LoadLibraryA),
-            (addr_LoadLibraryW, LoadLibraryW),
-            (addr_LoadLibraryExA, LoadLibraryExA),
-            (addr_LoadLibraryExW, LoadLibraryExW),
-            (addr_FreeLibrary, FreeLibrary)
-        );
+        match self.method {
+            Method::Inline => {
+                hook!("kernel32", Kernel32, pointers,
+                    (addr_LoadLibraryA, LoadLibraryA),
+                    (addr_LoadLibraryW, LoadLibraryW),
+                    (addr_LoadLibraryExA, LoadLibraryExA),
+                    (addr_LoadLibraryExW, LoadLibraryExW),
+                    (addr_FreeLibrary, FreeLibrary)
+                );
+            }
+            Method::Iat => {
+                // The detours call the functions in kernel32 directly.
+                unsafe {
+                    if let Some(addr) = addr_LoadLibraryA {
+                        pointers.LoadLibraryA.ptr = mem::transmute(addr);
+                    }
+                    if let Some(addr) = addr_LoadLibraryW {
+                        pointers.LoadLibraryW.ptr = mem::transmute(addr);
+                    }
+                    if let Some(addr) = addr_LoadLibraryExA {
+                        pointers.LoadLibraryExA.ptr = mem::transmute(addr);
+                    }
+                    if let Some(addr) = addr_LoadLibraryExW {
+                        pointers.LoadLibraryExW.ptr = mem::transmute(addr);
+                    }
+                    if let Some(addr) = addr_FreeLibrary {
+                        pointers.FreeLibrary.ptr = mem::transmute(addr);
+                    }
+                }
+
+                let detours = Kernel32::iat_detours(&pointers);
+                drop(pointers);
+
+                for module in windows::WINDOWS.get_loaded() {
+                    self.hook_imports(&windows::WINDOWS, &module, &detours);
+                }
+            }
+        }
     }
-}
 
-impl Kernel32 {
-    pub fn initial_hook() {
-        let modules = ModuleInfo::get_loaded();
+    // Y_KERNEL32_HOOKS=inline or iat picks the method. Otherwise, the imports are patched if
+    // another tool has already put a jump at the start of a function. The system's own stubs use
+    // `jmp [address]`, so they don't count.
+    fn choose_method<I>(mut functions: I) -> Method
+        where I: Iterator<Item = LPVOID>
+    {
+        match env::var("Y_KERNEL32_HOOKS") {
+            Ok(ref method) if method == "inline" => return Method::Inline,
+            Ok(ref method) if method == "iat" => return Method::Iat,
+            _ => {}
+        }
 
-        let mut hooked_something = false;
+        let patched = functions.any(|function| {
+            let first = unsafe { *(function as *const u8) };
+            first == 0xE9 || first == 0xEB
+        });
+
+        if patched { Method::Iat } else { Method::Inline }
+    }
 
-        for hook in HOOKS.iter() {
-            if let Some(module) = hook.read().unwrap().pick_best_hook_target(&modules) {
-                hook.write().unwrap().hook(module);
-                hooked_something = true;
+    fn hook_imports(&mut self,
+                    provider: &ModuleProvider,
+                    module: &ModuleInfo,
+                    detours: &[(&'static str, LPVOID)]) {
+        if self.iat_hooks.iter().any(|hook| hook.module == module.handle) ||
+           !IAT_MODULES.iter().any(|matcher| matcher.matches(provider, module)) {
+            return;
+        }
+
+        for &(name, detour) in detours {
+            match iat::hook(module, "kernel32.dll", name, detour) {
+                Ok(hooks) => self.iat_hooks.extend(hooks),
+                Err(iat::Error::NotImported) => {}
+                Err(err) => {
+                    error!(target: "kernel32",
+                           "Error hooking the import of {} in {:p}: {}",
+                           name,
+                           module.base,
+                           err)
+                }
             }
         }
 
-        if hooked_something {
-            features::refresh();
+        if self.iat_hooks.iter().any(|hook| hook.module == module.handle) {
+            debug!(target: "kernel32", "Hooked the imports of {:p}", module.base);
         }
     }
 
-    fn hook_module(handle: HMODULE) {
-        if let Some(module) = utils::get_module_info(handle) {
-            let mut hooked_something = false;
+    // Patches the imports of a newly loaded module.
+    fn hook_imports_of(&mut self, provider: &ModuleProvider, handle: HMODULE) {
+        if self.method != Method::Iat {
+            return;
+        }
 
-            for hook in HOOKS.iter() {
-                if hook.read().unwrap().should_hook(&module) {
-                    let mut hook = hook.write().unwrap();
+        if let Some(module) = provider.get_module_info(handle) {
+            let detours = Kernel32::iat_detours(&POINTERS.read().unwrap());
+            self.hook_imports(provider, &module, &detours);
+        }
+    }
 
-                    if hook.module_info().is_some() {
-                        hook.unhook();
-                    }
+    // The patched imports of a module are gone along with it.
+    fn forget_unloaded_imports(&mut self, provider: &ModuleProvider, handle: HMODULE) {
+        if provider.get_module_info(handle).is_none() {
+            self.iat_hooks.retain(|hook| hook.module != handle);
+        }
+    }
 
-                    hook.hook(&module);
+    pub fn unhook(&mut self) {
+        let mut pointers = POINTERS.write().unwrap();
 
-                    hooked_something = true;
+        match self.method {
+            Method::Inline => {
+                unhook!("kernel32", pointers,
+                    LoadLibraryA,
+                    LoadLibraryW,
+                    LoadLibraryExA,
+                    LoadLibraryExW,
+                    FreeLibrary
+                );
+            }
+            Method::Iat => {
+                for hook in self.iat_hooks.drain(..) {
+                    if let Err(err) = unsafe { hook.unhook() } {
+                        error!(target: "kernel32", "Error restoring an import: {}", err);
+                    }
                 }
             }
+        }
 
-            if hooked_something {
-                features::refresh();
-            }
+        *pointers = Kernel32::default();
+        *self = Self::default();
+    }
+}
+
+impl Kernel32 {
+    pub fn initial_hook() {
+        Kernel32::initial_hook_with(&windows::WINDOWS);
+
+        // What changes later is printed by y_status.
+        status::log();
+    }
+
+    pub fn initial_hook_with(provider: &ModuleProvider) {
+        if registry::hook_loaded(&registry::ordered(), provider) {
+            features::refresh();
         }
     }
 
-    fn unhook_module(handle: HMODULE) {
-        let mut unhooked_something = false;
+    // The imports to patch with Method::Iat: the functions that were found, with their detours.
+    fn iat_detours(pointers: &Kernel32) -> Vec<(&'static str, LPVOID)> {
+        let mut rv = Vec::new();
 
-        for hook in HOOKS.iter() {
-            let hook_read = hook.read().unwrap();
+        if !pointers.LoadLibraryA.is_default() {
+            rv.push(("LoadLibraryA", Kernel32::MyLoadLibraryA as LPVOID));
+        }
+        if !pointers.LoadLibraryW.is_default() {
+            rv.push(("LoadLibraryW", Kernel32::MyLoadLibraryW as LPVOID));
+        }
+        if !pointers.LoadLibraryExA.is_default() {
+            rv.push(("LoadLibraryExA", Kernel32::MyLoadLibraryExA as LPVOID));
+        }
+        if !pointers.LoadLibraryExW.is_default() {
+            rv.push(("LoadLibraryExW", Kernel32::MyLoadLibraryExW as LPVOID));
+        }
+        if !pointers.FreeLibrary.is_default() {
+            rv.push(("FreeLibrary", Kernel32::MyFreeLibrary as LPVOID));
+        }
 
-            if hook_read.module_info().is_some() {
-                if hook_read.module_info().unwrap().handle == handle {
-                    drop(hook_read);
-                    hook.write().unwrap().unhook();
-                    unhooked_something = true;
-                }
-            }
+        rv
+    }
+
+    fn hook_module(handle: HMODULE) {
+        MODULE.write().unwrap().hook_imports_of(&windows::WINDOWS, handle);
+        Kernel32::hook_module_with(&windows::WINDOWS, handle);
+    }
+
+    // See registry::hook_module().
+    pub fn hook_module_with(provider: &ModuleProvider, handle: HMODULE) {
+        if registry::hook_module(&registry::ordered(), provider, handle) {
+            features::refresh();
         }
+    }
 
-        if unhooked_something {
+    // See registry::unhook_module().
+    fn unhook_module(handle: HMODULE) {
+        if registry::unhook_module(&registry::ordered(), handle) {
             features::refresh();
         }
     }
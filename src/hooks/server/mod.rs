@@ -2,10 +2,18 @@ use features;
 use function::Function;
 use hookable::*;
 use libc::*;
-use moduleinfo::ModuleInfo;
+use matcher::ModuleMatcher;
+use moduleinfo::{AddressResult, ModuleInfo};
+use moduleinfo::windows;
+use registry;
+use signature::Batch;
+use status::{self, FunctionStatus};
 use std::sync::RwLock;
 use winapi;
 
+pub mod signatures;
+use self::signatures::*;
+
 lazy_static! {
     pub static ref MODULE: RwLock<ServerModule> = RwLock::new(ServerModule::default());
     static ref DATA: RwLock<Data> = RwLock::new(Data::default());
@@ -21,6 +29,11 @@ struct Data {
 pub struct ServerModule {
     module_info: Option<ModuleInfo>,
     current_name_index: Option<usize>,
+    functions: Vec<FunctionStatus>,
+}
+
+pub fn register() {
+    registry::register("server", &*MODULE, &[]);
 }
 
 hook_struct! {
@@ -39,7 +52,11 @@ hook_struct! {
             let mut orig_oldbuttons = 0;
             let mut oldbuttons = 0 as *mut c_int;
 
-            if features::autojump() {
+            // Read once, since oldbuttons is only set up when autojump is on and it can be toggled
+            // while the original runs.
+            let autojump = features::autojump();
+
+            if autojump {
                 let pointers = POINTERS.read().unwrap();
 
                 let mv = unsafe { *((this as *mut u8).offset(pointers.off_mv) as *mut *mut u8) };
@@ -66,7 +83,7 @@ hook_struct! {
 
             server.inside_checkjumpbutton = false;
 
-            if features::autojump() {
+            if autojump {
                 if !server.jumped_last_tick {
                     // We didn't jump this tick, restore the original jump button state.
                     unsafe {
@@ -90,44 +107,59 @@ hook_struct! {
     }
 }
 
-pattern!(CHL1GameMovement__CheckJumpButton
-    0x83 0xEC 0x14 0x53 0x56 0x8B 0xF1 0x57 0x8B 0x7E 0x08 0x85 0xFF 0x74 0x12 0x8B 0x07 0x8B 0xCF 0xFF 0x90 0x60 0x01 0x00 0x00 0x84 0xC0 0x74 0x04 0x8B 0xCF 0xEB
-);
-
-pattern!(CGameMovement__FinishGravity
-    0x8B 0x51 0x08 0xD9 0x82 0xB0 0x0B 0x00 0x00 0xD8 0x1D ?? ?? ?? ?? 0xDF 0xE0 0xF6 0xC4 0x44 0x7A 0x4D 0xD9 0x82 0x08 0x02 0x00 0x00 0xD8 0x1D
-);
-
 impl Hookable for ServerModule {
     fn module_info(&self) -> Option<&ModuleInfo> {
         self.module_info.as_ref()
     }
 
+    fn functions(&self) -> &[FunctionStatus] {
+        &self.functions
+    }
+
     fn hook(&mut self, module_info: &ModuleInfo) {
         self.module_info = Some(module_info.clone());
 
-        self.current_name_index = self.compute_current_name_index(module_info);
+        self.current_name_index = self.compute_current_name_index(&windows::WINDOWS, module_info);
 
         debug!(target: "server", "Base: {:p}; size = {}", module_info.base, module_info.size);
 
+        let batch = Batch::new(module_info, SIGNATURES);
         let addr_CHL1GameMovement__CheckJumpButton =
-            module_info.find(CHL1GameMovement__CheckJumpButton);
-        let addr_CGameMovement__FinishGravity = module_info.find(CGameMovement__FinishGravity);
+            CHL1GameMovement__CheckJumpButton.find_in(&batch);
+        let addr_CGameMovement__FinishGravity = CGameMovement__FinishGravity.find_in(&batch);
 
-        print_addrs!("server",
-            (addr_CHL1GameMovement__CheckJumpButton, "CHL1GameMovement::CheckJumpButton()"),
-            (addr_CGameMovement__FinishGravity, "CGameMovement::FinishGravity()")
+        self.functions = print_addrs!("server",
+            (addr_CHL1GameMovement__CheckJumpButton, CHL1GameMovement__CheckJumpButton.name),
+            (addr_CGameMovement__FinishGravity, CGameMovement__FinishGravity.name)
         );
 
         let mut pointers = POINTERS.write().unwrap();
 
-        pointers.off_mv = 4;
-        pointers.off_oldbuttons = 40;
+        // The detour needs the offsets of the matched variant, so an address found any other way
+        // can't be hooked.
+        let mut checkjumpbutton = None;
+
+        if let Ok(found) = addr_CHL1GameMovement__CheckJumpButton {
+            match found.variant() {
+                Some(variant) => {
+                    pointers.off_mv = variant.offsets.mv;
+                    pointers.off_oldbuttons = variant.offsets.oldbuttons;
+                    checkjumpbutton = Some(found.address);
+                }
+                None => {
+                    let err = "No offsets for this build, not hooking it".to_owned();
+                    warn!(target: "server", "{}: {}", CHL1GameMovement__CheckJumpButton.name, err);
+                    status::record_hooks(&mut self.functions, vec![(found.address, Err(err))]);
+                }
+            }
+        }
 
-        hook!("server", Server, pointers,
-            (addr_CHL1GameMovement__CheckJumpButton, CHL1GameMovement__CheckJumpButton),
-            (addr_CGameMovement__FinishGravity, CGameMovement__FinishGravity)
+        let hooked = hook!("server", Server, pointers,
+            (checkjumpbutton, CHL1GameMovement__CheckJumpButton),
+            (addr_CGameMovement__FinishGravity.address(), CGameMovement__FinishGravity)
         );
+
+        status::record_hooks(&mut self.functions, hooked);
     }
 
     fn unhook(&mut self) {
@@ -142,6 +174,13 @@ impl Hookable for ServerModule {
         *pointers = Server::default();
         *self = Self::default();
     }
+
+    fn set_hook_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let target = try!(Server::set_hook_enabled(name, enabled));
+        status::record_enabled(&mut self.functions, target, enabled);
+
+        Ok(())
+    }
 }
 
 impl HookableOrderedNameFilter for ServerModule {
@@ -149,8 +188,24 @@ impl HookableOrderedNameFilter for ServerModule {
         self.current_name_index
     }
 
-    fn get_names(&self) -> &[&'static str] {
-        const NAMES: &'static [&'static str] = &[ "server.dll" ];
-        NAMES
+    fn get_matchers(&self) -> &[ModuleMatcher] {
+        // Prefer HL:S when the modules of several games are loaded.
+        const MATCHERS: &'static [ModuleMatcher] = &[
+            ModuleMatcher::PathSuffix("hl1/bin/server.dll"),
+            ModuleMatcher::Name("server.dll"),
+        ];
+        MATCHERS
+    }
+}
+
+impl Server {
+    // Turns the detour of a function like "CGameMovement__FinishGravity" on or off.
+    // Returns the hooked address.
+    pub fn set_hook_enabled(name: &str, enabled: bool) -> Result<winapi::LPVOID, String> {
+        let pointers = POINTERS.read().unwrap();
+        set_hook_enabled!(pointers, name, enabled,
+            CHL1GameMovement__CheckJumpButton,
+            CGameMovement__FinishGravity
+        )
     }
 }
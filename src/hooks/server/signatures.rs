@@ -0,0 +1,42 @@
+use signature::{AnySignature, Signature, Variant};
+
+pattern!(CHL1GameMovement__CheckJumpButton_OOE
+    0x83 0xEC 0x14 0x53 0x56 0x8B 0xF1 0x57 0x8B 0x7E 0x08 0x85 0xFF 0x74 0x12 0x8B 0x07 0x8B 0xCF 0xFF 0x90 0x60 0x01 0x00 0x00 0x84 0xC0 0x74 0x04 0x8B 0xCF 0xEB
+);
+
+pattern!(CGameMovement__FinishGravity_OOE
+    0x8B 0x51 0x08 0xD9 0x82 0xB0 0x0B 0x00 0x00 0xD8 0x1D ?? ?? ?? ?? 0xDF 0xE0 0xF6 0xC4 0x44 0x7A 0x4D 0xD9 0x82 0x08 0x02 0x00 0x00 0xD8 0x1D
+);
+
+pub struct CheckJumpButtonOffsets {
+    pub mv: isize,
+    pub oldbuttons: isize,
+}
+
+pub static CHL1GameMovement__CheckJumpButton: Signature<CheckJumpButtonOffsets> = Signature {
+    name: "CHL1GameMovement::CheckJumpButton()",
+    variants: &[
+        Variant {
+            name: "HL:S OOE",
+            pattern: CHL1GameMovement__CheckJumpButton_OOE,
+            offsets: CheckJumpButtonOffsets {
+                mv: 4,
+                oldbuttons: 40,
+            },
+        },
+    ],
+    string_ref: None,
+};
+
+pub static CGameMovement__FinishGravity: Signature = Signature {
+    name: "CGameMovement::FinishGravity()",
+    variants: &[
+        Variant { name: "HL:S OOE", pattern: CGameMovement__FinishGravity_OOE, offsets: () },
+    ],
+    string_ref: None,
+};
+
+pub static SIGNATURES: &'static [&'static AnySignature] = &[
+    &CHL1GameMovement__CheckJumpButton,
+    &CGameMovement__FinishGravity,
+];
@@ -0,0 +1,150 @@
+// The import address table entries of a function, wherever the module imports it from.
+
+use moduleinfo::ModuleInfo;
+use moduleinfo::pe;
+use std::{error, fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    NotImported,
+    Pe(pe::Error),
+    Memory(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotImported => write!(f, "the module doesn't import the function"),
+            Error::Pe(ref err) => write!(f, "error parsing the module: {}", err),
+            Error::Memory(ref err) => write!(f, "memory error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NotImported => "the module doesn't import the function",
+            Error::Pe(ref err) => err.description(),
+            Error::Memory(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::NotImported => None,
+            Error::Pe(ref err) => Some(err),
+            Error::Memory(ref err) => Some(err),
+        }
+    }
+}
+
+// The addresses of the entries that import the function from the DLL, which is compared ignoring
+// the case. Imports by ordinal don't have a name, so they're never found.
+pub fn find_entries(module_info: &ModuleInfo,
+                    dll: &str,
+                    function: &str)
+                    -> Result<Vec<usize>, Error> {
+    let pe = try!(module_info.pe().map_err(Error::Pe));
+    let base = module_info.base as usize;
+    let dll = dll.to_lowercase();
+
+    let entries = pe.imports()
+                    .into_iter()
+                    .filter(|import| {
+                        import.module.to_lowercase() == dll &&
+                        import.name.as_ref().map_or(false, |name| name == function)
+                    })
+                    .map(|import| base + import.iat_rva)
+                    .collect::<Vec<usize>>();
+
+    if entries.is_empty() {
+        return Err(Error::NotImported);
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use moduleinfo::pe::builder::*;
+    use moduleinfo::provider::Fake;
+    use super::*;
+
+    fn importing() -> Vec<u8> {
+        let imports = import_directory(0x2000,
+                                       &[("KERNEL32.dll",
+                                          &[TestImport::Name("LoadLibraryA"),
+                                            TestImport::Ordinal(5),
+                                            TestImport::Name("FreeLibrary")]),
+                                         ("USER32.dll", &[TestImport::Name("MessageBoxW")]),
+                                         // Imported again, through another descriptor.
+                                         ("kernel32.DLL", &[TestImport::Name("FreeLibrary")])]);
+        importing_image(&[0xC3], &imports)
+    }
+
+    fn not_imported(result: Result<Vec<usize>, Error>) -> bool {
+        match result {
+            Err(Error::NotImported) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn find_entries_by_name() {
+        let mut provider = Fake::new();
+        let module = provider.add("C:/hl2/bin/engine.dll", importing());
+        let pe = module.pe().unwrap();
+
+        let iat_rvas = |function: &str| {
+            pe.imports()
+              .into_iter()
+              .filter(|import| import.name.as_ref().map_or(false, |name| name == function))
+              .map(|import| module.base as usize + import.iat_rva)
+              .collect::<Vec<usize>>()
+        };
+
+        let entries = find_entries(&module, "kernel32.dll", "LoadLibraryA").unwrap();
+        assert_eq!(entries, iat_rvas("LoadLibraryA"));
+        assert_eq!(entries.len(), 1);
+
+        // The entries hold what the loader put there.
+        let entry = entries[0] - module.base as usize;
+        assert_eq!(pe.read_u32(entry), Some(imported_address(0, 0)));
+
+        let entries = find_entries(&module, "KERNEL32.DLL", "FreeLibrary").unwrap();
+        assert_eq!(entries, iat_rvas("FreeLibrary"));
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(find_entries(&module, "user32.dll", "MessageBoxW").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn find_entries_not_imported() {
+        let mut provider = Fake::new();
+        let module = provider.add("C:/hl2/bin/engine.dll", importing());
+
+        // Imported by ordinal 5, which has no name to compare.
+        assert!(not_imported(find_entries(&module, "kernel32.dll", "5")));
+        // From another DLL.
+        assert!(not_imported(find_entries(&module, "user32.dll", "LoadLibraryA")));
+        // A DLL that isn't imported at all.
+        assert!(not_imported(find_entries(&module, "psapi.dll", "GetModuleInformation")));
+        assert!(not_imported(find_entries(&module, "kernel32", "LoadLibraryA")));
+
+        // A module without imports.
+        let module = provider.add("C:/hl2/bin/client.dll", build_image(&[&[0xC3]]));
+        assert!(not_imported(find_entries(&module, "kernel32.dll", "LoadLibraryA")));
+    }
+
+    #[test]
+    fn find_entries_unparsable() {
+        let mut provider = Fake::new();
+        let module = provider.add("C:/hl2/bin/engine.dll", vec![0; 0x100]);
+
+        match find_entries(&module, "kernel32.dll", "LoadLibraryA") {
+            Err(Error::Pe(_)) => {}
+            _ => panic!("expected a PE error"),
+        }
+    }
+}
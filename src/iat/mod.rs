@@ -0,0 +1,67 @@
+// Hooking functions by swapping the addresses in the import address tables of the modules that
+// call them. The code of the functions stays intact, so this doesn't conflict with other tools
+// patching it, but only calls from the patched modules are intercepted.
+
+use moduleinfo::ModuleInfo;
+use std::{io, mem, slice};
+use utils;
+use winapi::*;
+
+// Finding the entries only reads the image, so the offline tools can test it.
+mod entries;
+pub use self::entries::*;
+
+pub struct IatHook {
+    pub module: HMODULE,
+    // The address of the import address table entry.
+    entry: usize,
+    original: usize,
+}
+
+unsafe impl Send for IatHook {}
+unsafe impl Sync for IatHook {}
+
+unsafe fn write_entry(entry: usize, value: usize) -> io::Result<()> {
+    let bytes = slice::from_raw_parts(&value as *const usize as *const u8,
+                                      mem::size_of::<usize>());
+    utils::write_memory(entry, bytes)
+}
+
+// Points every import of the function from the DLL (like "kernel32.dll") to the detour, see
+// find_entries(). Modules sometimes import a function more than once.
+pub fn hook(module_info: &ModuleInfo,
+            dll: &str,
+            function: &str,
+            detour: LPVOID)
+            -> Result<Vec<IatHook>, Error> {
+    let entries = try!(find_entries(module_info, dll, function));
+
+    let mut rv = Vec::new();
+
+    for entry in entries {
+        let original = unsafe { *(entry as *const usize) };
+
+        if let Err(err) = unsafe { write_entry(entry, detour as usize) } {
+            for hook in rv {
+                let _ = unsafe { IatHook::unhook(hook) };
+            }
+
+            return Err(Error::Memory(err));
+        }
+
+        rv.push(IatHook {
+            module: module_info.handle,
+            entry: entry,
+            original: original,
+        });
+    }
+
+    Ok(rv)
+}
+
+impl IatHook {
+    // Puts the original address back. The module has to still be loaded.
+    pub unsafe fn unhook(self) -> Result<(), Error> {
+        write_entry(self.entry, self.original).map_err(Error::Memory)
+    }
+}
@@ -0,0 +1,129 @@
+use moduleinfo::pe::Pe;
+use x86;
+
+// How far into CreateInterface() the list head is looked for.
+const MAX_INSTRUCTIONS: usize = 32;
+// CreateInterface() is sometimes a jump to the real implementation.
+const MAX_JUMPS: usize = 4;
+
+// Finds the RVA of s_pInterfaceRegs from the code of CreateInterface(): it's the first global that
+// is loaded with `mov eax, [addr]` or `mov reg, [addr]`. `base` is where the module is loaded,
+// since the addresses in the code are relocated.
+pub fn find_list_head(pe: &Pe, base: usize, create_interface: usize) -> Option<usize> {
+    let mut rva = create_interface;
+    let mut jumps = 0;
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let code = match pe.read(rva, 16) {
+            Some(code) => code,
+            None => return None,
+        };
+
+        let instruction = match x86::decode(code) {
+            Some(instruction) => instruction,
+            None => return None,
+        };
+
+        let next = rva + instruction.len;
+
+        let global = match (code[0], instruction.rel) {
+            (0xE9, Some(rel)) | (0xEB, Some(rel)) if jumps < MAX_JUMPS => {
+                jumps += 1;
+                rva = (next as isize + rel.read_signed(code)) as usize;
+                continue;
+            }
+            // The end of the function.
+            (0xC2, _) | (0xC3, _) | (0xE9, _) | (0xEB, _) => return None,
+            (0xA1, _) => instruction.imm.map(|imm| imm.read_signed(code) as u32),
+            // mod = 00, r/m = 101 is an absolute address.
+            (0x8B, _) if code[1] & 0xC7 == 0x05 => {
+                instruction.disp.map(|disp| disp.read_signed(code) as u32)
+            }
+            _ => None,
+        };
+
+        if let Some(address) = global {
+            let global_rva = (address as usize).wrapping_sub(base);
+
+            if pe.section_for_rva(global_rva).map_or(false, |section| section.is_writable()) {
+                return Some(global_rva);
+            }
+        }
+
+        rva = next;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moduleinfo::pe::Layout;
+    use moduleinfo::pe::builder::*;
+
+    const DATA: u32 = IMAGE_BASE + 0x3000;
+    const RDATA: u32 = IMAGE_BASE + 0x2000;
+
+    fn find(code: &[u8], base: u32) -> Option<usize> {
+        let image = build_image(&[code, &[0; 0x10], &[0; 0x10]]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+        find_list_head(&pe, base as usize, 0x1000)
+    }
+
+    // The instruction bytes with the address after them, then the rest.
+    fn with_address(instruction: &[u8], address: u32, rest: &[u8]) -> Vec<u8> {
+        let mut code = instruction.to_vec();
+        code.extend_from_slice(&[address as u8,
+                                 (address >> 8) as u8,
+                                 (address >> 16) as u8,
+                                 (address >> 24) as u8]);
+        code.extend_from_slice(rest);
+        code
+    }
+
+    #[test]
+    fn direct() {
+        // push ebp; mov ebp, esp; mov eax, [s_pInterfaceRegs]
+        let code = with_address(&[0x55, 0x8B, 0xEC, 0xA1], DATA + 8, &[0x5D, 0xC3]);
+        assert_eq!(find(&code, IMAGE_BASE), Some(0x3008));
+
+        // mov esi, [s_pInterfaceRegs], after a global that isn't writable.
+        let code = with_address(&[0xA1], RDATA, &with_address(&[0x8B, 0x35], DATA + 4, &[0xC3]));
+        assert_eq!(find(&code, IMAGE_BASE), Some(0x3004));
+    }
+
+    #[test]
+    fn relocated() {
+        // The code refers to where the loader put the module.
+        let code = with_address(&[0xA1], 0x0400_3008, &[0xC3]);
+        assert_eq!(find(&code, 0x0400_0000), Some(0x3008));
+        assert_eq!(find(&code, IMAGE_BASE), None);
+    }
+
+    #[test]
+    fn jump_thunk() {
+        // jmp rel32 to 0x1010, then jmp short to 0x1020.
+        let mut code = with_address(&[0xE9], 0x0B, &[]);
+        code.resize(0x10, 0xCC);
+        code.extend_from_slice(&[0xEB, 0x0E]);
+        code.resize(0x20, 0xCC);
+        code.extend_from_slice(&with_address(&[0xA1], DATA, &[0xC3]));
+
+        assert_eq!(find(&code, IMAGE_BASE), Some(0x3000));
+    }
+
+    #[test]
+    fn not_found() {
+        // xor eax, eax; ret
+        assert_eq!(find(&[0x33, 0xC0, 0xC3], IMAGE_BASE), None);
+        // Only a global that isn't writable.
+        assert_eq!(find(&with_address(&[0xA1], RDATA, &[0xC3]), IMAGE_BASE), None);
+        // mov eax, [ecx] has no absolute address.
+        assert_eq!(find(&[0x8B, 0x01, 0xC3], IMAGE_BASE), None);
+        // A jump to itself gives up after a few jumps.
+        assert_eq!(find(&[0xEB, 0xFE], IMAGE_BASE), None);
+        // No ret within the instruction limit.
+        assert_eq!(find(&[0x90; 0x40], IMAGE_BASE), None);
+    }
+}
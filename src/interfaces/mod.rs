@@ -0,0 +1,153 @@
+// Source modules register the interfaces they expose in a linked list, s_pInterfaceRegs, which
+// their exported CreateInterface() walks. Finding the list head lets us see every interface
+// instead of only the ones we already know the names of.
+
+use libc::{c_char, c_int, c_void};
+use moduleinfo::ModuleInfo;
+use moduleinfo::provider::ModuleProvider;
+use moduleinfo::windows;
+use std::{error, fmt, mem, ptr};
+use std::ffi::{CStr, CString};
+
+// Finding the list head only reads the image, so the offline tools can share it.
+mod list_head;
+pub use self::list_head::find_list_head;
+
+// A corrupted list could loop forever.
+const MAX_INTERFACES: usize = 4096;
+
+type CreateInterfaceFn = extern "C" fn(name: *const c_char, return_code: *mut c_int)
+                                        -> *mut c_void;
+type InstantiateInterfaceFn = extern "C" fn() -> *mut c_void;
+
+#[repr(C)]
+struct InterfaceReg {
+    create_fn: InstantiateInterfaceFn,
+    name: *const c_char,
+    next: *mut InterfaceReg,
+}
+
+#[derive(Clone)]
+pub struct Interface {
+    pub name: String,
+    // Interfaces exposed with EXPOSE_INTERFACE create a new object on every call, so this is only
+    // for showing where the interface comes from.
+    pub create_fn: InstantiateInterfaceFn,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    NoCreateInterface,
+    NoRegistry,
+    NotFound { name: String, available: Vec<String> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoCreateInterface => write!(f, "the module doesn't export CreateInterface()"),
+            Error::NoRegistry => write!(f, "couldn't find the interface list"),
+            Error::NotFound { ref name, ref available } => {
+                if available.is_empty() {
+                    write!(f, "{} not found, no interfaces are registered", name)
+                } else {
+                    write!(f, "{} not found, available: {}", name, available.join(", "))
+                }
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NoCreateInterface => "the module doesn't export CreateInterface()",
+            Error::NoRegistry => "couldn't find the interface list",
+            Error::NotFound { .. } => "interface not found",
+        }
+    }
+}
+
+// Every interface registered in the module, in the list order.
+pub fn list(module_info: &ModuleInfo) -> Result<Vec<Interface>, Error> {
+    let create_interface = try!(module_info.get_function("CreateInterface")
+        .ok_or(Error::NoCreateInterface));
+
+    let pe = try!(module_info.pe().map_err(|_| Error::NoRegistry));
+    let base = module_info.base as usize;
+
+    let head = try!(find_list_head(&pe, base, (create_interface as usize).wrapping_sub(base))
+        .ok_or(Error::NoRegistry));
+
+    let mut rv = Vec::new();
+
+    unsafe {
+        let mut reg = *((base + head) as *const *mut InterfaceReg);
+
+        while !reg.is_null() && rv.len() < MAX_INTERFACES {
+            if !(*reg).name.is_null() {
+                rv.push(Interface {
+                    name: CStr::from_ptr((*reg).name).to_string_lossy().into_owned(),
+                    create_fn: (*reg).create_fn,
+                });
+            }
+
+            reg = (*reg).next;
+        }
+    }
+
+    Ok(rv)
+}
+
+// The interfaces of every loaded module that has any, by the module file name.
+pub fn list_all() -> Vec<(String, Vec<Interface>)> {
+    ModuleInfo::get_loaded()
+        .into_iter()
+        .filter_map(|module_info| {
+            let interfaces = match list(&module_info) {
+                Ok(interfaces) => interfaces,
+                Err(Error::NoCreateInterface) => return None,
+                Err(err) => {
+                    warn!(target: "interfaces", "{:p}: {}", module_info.base, err);
+                    return None;
+                }
+            };
+
+            let name = windows::WINDOWS.get_module_path(module_info.handle)
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| format!("{:p}", module_info.base));
+
+            Some((name, interfaces))
+        })
+        .collect()
+}
+
+// The error for an interface version that CreateInterface() didn't return, with the versions the
+// module does have.
+pub fn not_found(module_info: &ModuleInfo, name: &str) -> Error {
+    match list(module_info) {
+        Ok(interfaces) => {
+            Error::NotFound {
+                name: name.to_owned(),
+                available: interfaces.into_iter().map(|interface| interface.name).collect(),
+            }
+        }
+        Err(err) => err,
+    }
+}
+
+// Creates the interface with the exact version through the module's CreateInterface().
+pub fn create<T>(module_info: &ModuleInfo, name: &str) -> Result<*mut T, Error> {
+    let create_interface = try!(module_info.get_function("CreateInterface")
+        .ok_or(Error::NoCreateInterface));
+    let create_interface: CreateInterfaceFn = unsafe { mem::transmute(create_interface) };
+
+    let c_name = try!(CString::new(name).map_err(|_| not_found(module_info, name)));
+    let interface = create_interface(c_name.as_ptr(), ptr::null_mut());
+
+    if interface.is_null() {
+        Err(not_found(module_info, name))
+    } else {
+        Ok(interface as *mut T)
+    }
+}
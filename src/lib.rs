@@ -23,36 +23,57 @@ use winapi::*;
 #[macro_use]
 mod macros;
 
+mod cache;
+mod detour;
 mod features;
 mod function;
 mod hookable;
 mod hooks {
+    pub mod client;
     pub mod engine;
     pub mod kernel32;
     pub mod server;
 }
+mod iat;
+mod interfaces;
 mod logger;
-mod minhook;
+mod matcher;
 mod moduleinfo;
 use moduleinfo::ModuleInfo;
 mod pattern;
+mod registry;
+mod signature;
+mod status;
 mod utils;
+// Nothing hooks interfaces yet.
+#[allow(dead_code)]
+mod vmt;
+mod x86;
 
 const DLL_PROCESS_ATTACH: DWORD = 1;
 const DLL_PROCESS_DETACH: DWORD = 0;
 
 #[no_mangle]
-pub extern "stdcall" fn DllMain(instance: HINSTANCE, reason: DWORD, _reserved: LPVOID) -> BOOL {
+pub extern "stdcall" fn DllMain(instance: HINSTANCE, reason: DWORD, reserved: LPVOID) -> BOOL {
     match reason {
         DLL_PROCESS_ATTACH => {
             unsafe {
                 kernel32::DisableThreadLibraryCalls(instance);
             }
 
+            utils::set_own_module(instance);
+
             thread::spawn(main_thread);
         }
-        DLL_PROCESS_DETACH => {
-            minhook::uninitialize();
+        // A non-null reserved means that the process is exiting. The other threads were killed
+        // wherever they were, maybe holding the locks or inside the detours, and the memory goes
+        // away anyway, so only FreeLibrary() restores the hooks.
+        DLL_PROCESS_DETACH if reserved.is_null() => {
+            if let Ok(mut module) = hooks::kernel32::MODULE.try_write() {
+                module.unhook();
+            }
+
+            detour::uninitialize();
         }
         _ => {}
     }
@@ -83,6 +104,14 @@ fn initialize() -> Result<(), String> {
     debug!(target: "", "Debug");
     trace!(target: "", "Trace");
 
+    info!(target: "", "Using the {} detour backend.", detour::backend().name());
+
+    features::init();
+
+    hooks::engine::register();
+    hooks::server::register();
+    hooks::client::register();
+
     if let Some(kernel32) = ModuleInfo::get("kernel32.dll") {
         hooks::kernel32::MODULE.write().unwrap().hook(&kernel32);
         hooks::kernel32::Kernel32::initial_hook();
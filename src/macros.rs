@@ -129,28 +129,63 @@ macro_rules! gen_function_impls {
 }
 
 macro_rules! pattern {
-    ($name:ident $(; $byte:tt $mask:expr)* , ?? $($rest:tt)*) => (
-        pattern!($name $(; $byte $mask)* ; 0x00 false , $($rest)*);
+    (@munch $name:ident [$($bytes:tt)*] [$($captures:tt)*] ($($offset:tt)*) ?? $($rest:tt)*) => (
+        pattern!(@munch $name
+                 [$($bytes)* (0x00, 0x00),]
+                 [$($captures)*]
+                 ($($offset)* + 1)
+                 $($rest)*);
     );
 
-    ($name:ident $(; $byte:tt $mask:expr)* , $b:tt $($rest:tt)*) => (
-        pattern!($name $(; $byte $mask)* ; $b true , $($rest)*);
+    (@munch $name:ident [$($bytes:tt)*] [$($captures:tt)*] ($($offset:tt)*) @ $capture:ident $($rest:tt)*) => (
+        pattern!(@munch $name
+                 [$($bytes)*]
+                 [$($captures)* $crate::pattern::Capture {
+                     name: ::std::borrow::Cow::Borrowed(stringify!($capture)),
+                     offset: $($offset)*,
+                 },]
+                 ($($offset)*)
+                 $($rest)*);
     );
 
-    ($name:ident $(; $byte:tt $mask:expr)+ ,) => (
-        pub const $name: $crate::pattern::Pattern = $crate::pattern::Pattern(&[$(($byte, $mask)),*]);
+    // A byte that only needs to match under a mask, e.g. (0x80 & 0xF0) for the `8?` nibble. The
+    // string syntax can't be taken here, see the README.
+    (@munch $name:ident [$($bytes:tt)*] [$($captures:tt)*] ($($offset:tt)*) ($b:tt & $m:tt) $($rest:tt)*) => (
+        pattern!(@munch $name
+                 [$($bytes)* ($b & $m, $m),]
+                 [$($captures)*]
+                 ($($offset)* + 1)
+                 $($rest)*);
+    );
+
+    (@munch $name:ident [$($bytes:tt)*] [$($captures:tt)*] ($($offset:tt)*) $b:tt $($rest:tt)*) => (
+        pattern!(@munch $name
+                 [$($bytes)* ($b, 0xFF),]
+                 [$($captures)*]
+                 ($($offset)* + 1)
+                 $($rest)*);
+    );
+
+    (@munch $name:ident [$($bytes:tt)+] [$($captures:tt)*] ($($offset:tt)*)) => (
+        pub const $name: $crate::pattern::Pattern<'static> = $crate::pattern::Pattern {
+            bytes: &[$($bytes)*],
+            captures: &[$($captures)*],
+        };
     );
 
     ($name:ident $($rest:tt)+) => (
-        pattern!($name , $($rest)*);
+        pattern!(@munch $name [] [] (0) $($rest)*);
     );
 }
 
+// Evaluates to the result for every address that was hooked, see status::record_hooks().
 macro_rules! hook {
     ($target:tt, $stype:ident, $s:ident, $(($ftarget:expr, $fname:ident)),+) => {{
+        let mut results = Vec::new();
+
         $(
             if let Some(ftarget) = $ftarget {
-                if let Err(err) = { interpolate_idents! {
+                let result = { interpolate_idents! {
                     let detour = $stype::[My $fname];
                     let trampoline = &mut $s.$fname.ptr;
 
@@ -158,20 +193,32 @@ macro_rules! hook {
                     let mut temp = *trampoline;
                     temp = detour;
 
-                    $crate::minhook::create_hook(ftarget, temp, trampoline)
+                    $crate::detour::create_hook(ftarget, temp, trampoline)
                         .map_err(|e| format!("Error creating hook: {}", e))
-                        .and($crate::minhook::queue_enable_hook(Some(ftarget))
+                        .and($crate::detour::queue_enable_hook(Some(ftarget))
                             .map_err(|e| format!("Error adding hook to enable queue: {}", e)))
-                } } {
+                } };
+
+                if let Err(ref err) = result {
                     error!(target: $target, "{}", err);
                 }
+
+                results.push((ftarget, result));
             }
         )*
 
-        if let Err(err) = $crate::minhook::apply_queued()
+        if let Err(err) = $crate::detour::apply_queued()
             .map_err(|e| format!("Error enabling queued hooks: {}", e)) {
             error!(target: $target, "{}", err);
+
+            for &mut (_, ref mut result) in &mut results {
+                if result.is_ok() {
+                    *result = Err(err.clone());
+                }
+            }
         }
+
+        results
     }}
 }
 
@@ -180,7 +227,7 @@ macro_rules! unhook {
         $(
             if !$s.$fname.is_default() {
                 if let Err(err) = {
-                    $crate::minhook::queue_disable_hook(Some($s.$fname.ptr as winapi::LPVOID))
+                    $crate::detour::queue_disable_hook(Some($s.$fname.ptr as winapi::LPVOID))
                             .map_err(|e| format!("Error adding hook to disable queue: {}", e))
                 } {
                     error!(target: $target, "{}", err);
@@ -188,7 +235,7 @@ macro_rules! unhook {
             }
         )*
 
-        if let Err(err) = $crate::minhook::apply_queued()
+        if let Err(err) = $crate::detour::apply_queued()
             .map_err(|e| format!("Error disabling queued hooks: {}", e)) {
             error!(target: $target, "{}", err);
         }
@@ -196,7 +243,7 @@ macro_rules! unhook {
         $(
             if !$s.$fname.is_default() {
                 if let Err(err) = {
-                    $crate::minhook::remove_hook(Some($s.$fname.ptr as winapi::LPVOID))
+                    $crate::detour::remove_hook(Some($s.$fname.ptr as winapi::LPVOID))
                             .map_err(|e| format!("Error removing hook: {}", e))
                 } {
                     error!(target: $target, "{}", err);
@@ -206,6 +253,35 @@ macro_rules! unhook {
     } }}
 }
 
+// Turns the detour of one of the functions on or off, by the function name. Evaluates to the
+// hooked address.
+macro_rules! set_hook_enabled {
+    ($s:ident, $name:expr, $enabled:expr, $($fname:ident),+) => {{
+        let name: &str = $name;
+        let enabled: bool = $enabled;
+        let mut result = Err(format!("{} has no detour", name));
+
+        $(
+            if name == stringify!($fname) {
+                result = if $s.$fname.is_default() {
+                    Err(format!("{} isn't hooked", name))
+                } else {
+                    $crate::detour::queue_set_enabled($s.$fname.ptr as winapi::LPVOID, enabled)
+                        .and_then(|target| $crate::detour::apply_queued().map(|_| target))
+                        .map_err(|e| {
+                            format!("Error {} the hook of {}: {}",
+                                    if enabled { "enabling" } else { "disabling" },
+                                    name,
+                                    e)
+                        })
+                };
+            }
+        )*
+
+        result
+    }}
+}
+
 macro_rules! cstr {
     ($s:expr) => ($s as *const _ as *const libc::c_char)
 }
@@ -231,28 +307,54 @@ macro_rules! con_command {
     } )
 }
 
+// Evaluates to the status of every address, see status::FunctionStatus.
 macro_rules! print_addrs {
-    ($target:tt, $(($addr:expr, $name:tt)),*) => {
+    ($target:tt, $(($addr:expr, $name:expr)),*) => {{
+        let mut functions = Vec::new();
+
         $(
-            match $addr {
-                Some(addr) => debug!(target: $target, "{:p} - {}", addr, $name),
-                None => warn!(target: $target, "<not found> - {}", $name),
+            // Borrowed, so the caller can still use it, and evaluated once.
+            let found = &$addr;
+            let result = $crate::moduleinfo::AddressResult::to_address_result(found);
+            let method = $crate::moduleinfo::AddressResult::method(found);
+
+            match result {
+                Ok(addr) => match method {
+                    Some(ref method) => {
+                        debug!(target: $target, "{:p} - {} [{}]", addr, $name, method)
+                    }
+                    None => debug!(target: $target, "{:p} - {}", addr, $name),
+                },
+                Err($crate::moduleinfo::FindError::NotFound) => {
+                    warn!(target: $target, "<not found> - {}", $name)
+                }
+                Err($crate::moduleinfo::FindError::Ambiguous(count)) => {
+                    warn!(target: $target, "<ambiguous ({} matches)> - {}", count, $name)
+                }
             };
+
+            functions.push($crate::status::FunctionStatus::new($name, result, method));
         )*
-    }
+
+        functions
+    }}
 }
 
 macro_rules! define_features {
-    ($(($fname:ident, $sname:ident, $text:tt)),*) => ( interpolate_idents! {
+    ($(($fname:ident, $sname:ident, $text:tt, [$($hookable:tt),*],
+        [$(($dhookable:tt, $dfunction:tt)),*])),*) => ( interpolate_idents! {
         $(
             static mut $sname: Feature = Feature {
                 name: $text,
-                enabled: false,
+                available: false,
+                wanted: true,
+                hookables: &[$($hookable),*],
+                detours: &[$(($dhookable, $dfunction)),*],
             };
 
             #[inline(always)]
             pub fn $fname() -> bool {
-                unsafe { $sname.enabled }
+                unsafe { $sname.available && $sname.wanted }
             }
         )*
 
@@ -263,5 +365,15 @@ macro_rules! define_features {
                 ),*
             ]
         };
+
+        unsafe fn feature_mut(key: &str) -> Option<&'static mut Feature> {
+            $(
+                if key == stringify!($fname) {
+                    return Some(&mut $sname);
+                }
+            )*
+
+            None
+        }
     } )
 }
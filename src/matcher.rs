@@ -0,0 +1,295 @@
+// Ways of recognizing a module, and picking the module to hook with them. Hookables list the
+// matchers in the order of preference, see HookableOrderedNameFilter.
+
+use moduleinfo::ModuleInfo;
+use moduleinfo::provider::ModuleProvider;
+use std::path::Path;
+
+// File names and paths are compared ignoring the case, like Windows does.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum ModuleMatcher {
+    Name(&'static str),
+    // A file name pattern, `*` matches any number of characters and `?` matches one.
+    Glob(&'static str),
+    // The trailing components of the path, like `hl1/bin/server.dll`. Either slash works.
+    PathSuffix(&'static str),
+    // A string from the version resource, like ("ProductName", "Half-Life*"). The value is a glob
+    // and, unlike file names, is case-sensitive.
+    Version(&'static str, &'static str),
+    // Every one of the matchers.
+    All(&'static [ModuleMatcher]),
+}
+
+impl ModuleMatcher {
+    pub fn matches(&self, provider: &ModuleProvider, module_info: &ModuleInfo) -> bool {
+        match *self {
+            ModuleMatcher::Name(_) | ModuleMatcher::Glob(_) | ModuleMatcher::PathSuffix(_) => {
+                provider.get_module_path(module_info.handle)
+                        .map_or(false, |path| self.matches_path(&path))
+            }
+            ModuleMatcher::Version(key, pattern) => {
+                module_info.version_info()
+                           .map_or(false, |info| {
+                               info.get(key).map_or(false, |value| glob(pattern, value))
+                           })
+            }
+            ModuleMatcher::All(matchers) => {
+                matchers.iter().all(|matcher| matcher.matches(provider, module_info))
+            }
+        }
+    }
+
+    // Paths that aren't valid Unicode are compared lossily, so they can still match everything
+    // but the invalid characters.
+    fn matches_path(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().to_lowercase().replace('\\', "/");
+        let filename = path.rsplit('/').next().unwrap_or("");
+
+        match *self {
+            ModuleMatcher::Name(name) => filename == name.to_lowercase(),
+            ModuleMatcher::Glob(pattern) => glob(&pattern.to_lowercase(), filename),
+            ModuleMatcher::PathSuffix(suffix) => {
+                let suffix = suffix.to_lowercase().replace('\\', "/");
+                path == suffix || path.ends_with(&format!("/{}", suffix))
+            }
+            _ => false,
+        }
+    }
+}
+
+pub trait HookableFilter {
+    fn should_hook(&self, provider: &ModuleProvider, module_info: &ModuleInfo) -> bool;
+    fn pick_best_hook_target<'a>(&self,
+                                 provider: &ModuleProvider,
+                                 modules: &'a [ModuleInfo])
+                                 -> Option<&'a ModuleInfo>;
+}
+
+// Picks the module that the first matcher matches, then the second one and so on, and switches to
+// a better match when it gets loaded.
+pub trait HookableOrderedNameFilter : HookableFilter {
+    fn get_current_name_index(&self) -> Option<usize>;
+    fn get_matchers(&self) -> &[ModuleMatcher];
+
+    fn compute_current_name_index(&self,
+                                  provider: &ModuleProvider,
+                                  module_info: &ModuleInfo)
+                                  -> Option<usize> {
+        self.get_matchers()
+            .iter()
+            .position(|matcher| matcher.matches(provider, module_info))
+    }
+}
+
+impl<T> HookableFilter for T where T: HookableOrderedNameFilter {
+    fn should_hook(&self, provider: &ModuleProvider, module_info: &ModuleInfo) -> bool {
+        let current_index = self.get_current_name_index();
+
+        if current_index == Some(0) {
+            // We already have the hook with the top priority.
+            return false;
+        }
+
+        let matchers = match current_index {
+            Some(index) => &self.get_matchers()[..index],
+            None => self.get_matchers(),
+        };
+
+        matchers.iter().any(|matcher| matcher.matches(provider, module_info))
+    }
+
+    fn pick_best_hook_target<'a>(&self,
+                                 provider: &ModuleProvider,
+                                 modules: &'a [ModuleInfo])
+                                 -> Option<&'a ModuleInfo> {
+        for matcher in self.get_matchers() {
+            if let Some(module) = modules.iter()
+                                         .find(|module| matcher.matches(provider, module)) {
+                return Some(module);
+            }
+        }
+
+        None
+    }
+}
+
+pub fn glob(pattern: &str, string: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let string = string.chars().collect::<Vec<char>>();
+
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match.
+    let mut backtrack = None;
+
+    while s < string.len() {
+        // The `*` has to be checked first, otherwise it would match a literal `*` and nothing else.
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, s));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == string[s]) {
+            p += 1;
+            s += 1;
+        } else if let Some((star, star_s)) = backtrack {
+            // Let the `*` eat one more character.
+            p = star + 1;
+            s = star_s + 1;
+            backtrack = Some((star, star_s + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use moduleinfo::provider::Fake;
+    use super::*;
+    use winapi::*;
+
+    const MATCHERS: &'static [ModuleMatcher] = &[
+        ModuleMatcher::PathSuffix("hl2/bin/server.dll"),
+        ModuleMatcher::Name("server.dll"),
+    ];
+
+    // Keeps track of the hooked module like the hookables in hooks do.
+    #[derive(Default)]
+    struct Target {
+        module_info: Option<ModuleInfo>,
+        current_name_index: Option<usize>,
+    }
+
+    impl HookableOrderedNameFilter for Target {
+        fn get_current_name_index(&self) -> Option<usize> {
+            self.current_name_index
+        }
+
+        fn get_matchers(&self) -> &[ModuleMatcher] {
+            MATCHERS
+        }
+    }
+
+    impl Target {
+        fn hook(&mut self, provider: &ModuleProvider, module_info: &ModuleInfo) {
+            self.module_info = Some(*module_info);
+            self.current_name_index = self.compute_current_name_index(provider, module_info);
+        }
+
+        fn handle(&self) -> Option<HMODULE> {
+            self.module_info.map(|module_info| module_info.handle)
+        }
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob("*", ""));
+        assert!(glob("*", "*a"));
+        assert!(glob("*a", "*a"));
+        assert!(glob("a*", "a*b"));
+        assert!(glob("*.dll", "server.dll"));
+        assert!(glob("server*.dll", "server_srv.dll"));
+        assert!(glob("?lient.dll", "client.dll"));
+        assert!(glob("*a*b", "xaxxab"));
+        assert!(glob("Half-Life*", "Half-Life 2"));
+        assert!(!glob("*.dll", "server.exe"));
+        assert!(!glob("?lient.dll", "lient.dll"));
+        assert!(!glob("*a", "ab"));
+        assert!(!glob("half-life*", "Half-Life 2"));
+        assert!(!glob("", "a"));
+    }
+
+    #[test]
+    fn match_paths_ignoring_case_and_slashes() {
+        let path = Path::new("C:\\Games\\Half-Life 2\\HL2\\bin\\Server.DLL");
+
+        assert!(ModuleMatcher::Name("server.dll").matches_path(path));
+        assert!(ModuleMatcher::Name("SERVER.dll").matches_path(path));
+        assert!(!ModuleMatcher::Name("bin/server.dll").matches_path(path));
+        assert!(!ModuleMatcher::Name("client.dll").matches_path(path));
+
+        assert!(ModuleMatcher::Glob("SERV*.dll").matches_path(path));
+        assert!(ModuleMatcher::Glob("*").matches_path(path));
+        assert!(!ModuleMatcher::Glob("*bin*").matches_path(path));
+
+        assert!(ModuleMatcher::PathSuffix("hl2/bin/server.dll").matches_path(path));
+        assert!(ModuleMatcher::PathSuffix("HL2\\BIN\\server.dll").matches_path(path));
+        assert!(ModuleMatcher::PathSuffix("Half-Life 2/hl2/bin/Server.dll").matches_path(path));
+        assert!(!ModuleMatcher::PathSuffix("l2/bin/server.dll").matches_path(path));
+        assert!(!ModuleMatcher::PathSuffix("ep2/bin/server.dll").matches_path(path));
+
+        let path = Path::new("c:/games/hl2/bin/server.dll");
+        assert!(ModuleMatcher::PathSuffix("C:\\Games\\HL2\\bin\\server.dll").matches_path(path));
+    }
+
+    #[test]
+    fn match_through_the_provider() {
+        let mut provider = Fake::new();
+        let server = provider.add("C:\\Games\\HL2\\bin\\SERVER.DLL", Vec::new());
+
+        assert!(ModuleMatcher::Name("server.dll").matches(&provider, &server));
+        assert!(ModuleMatcher::PathSuffix("hl2/bin/server.dll").matches(&provider, &server));
+        const ALL: &'static [ModuleMatcher] = &[
+            ModuleMatcher::Glob("*.DLL"),
+            ModuleMatcher::PathSuffix("bin/server.dll"),
+        ];
+        assert!(ModuleMatcher::All(ALL).matches(&provider, &server));
+
+        // Without a version resource nothing matches the version.
+        assert!(!ModuleMatcher::Version("ProductName", "*").matches(&provider, &server));
+
+        provider.remove(server.handle);
+        assert!(!ModuleMatcher::Name("server.dll").matches(&provider, &server));
+    }
+
+    #[test]
+    fn pick_best_hook_target_in_matcher_order() {
+        let mut provider = Fake::new();
+        provider.add("C:/Games/hl2/bin/client.dll", Vec::new());
+        let worse = provider.add("C:/Games/mod/bin/server.dll", Vec::new());
+        let better = provider.add("C:/Games/hl2/bin/server.dll", Vec::new());
+
+        let target = Target::default();
+        let modules = provider.get_loaded();
+        let best = target.pick_best_hook_target(&provider, &modules);
+        assert_eq!(best.map(|module| module.handle), Some(better.handle));
+
+        provider.remove(better.handle);
+        let modules = provider.get_loaded();
+        let best = target.pick_best_hook_target(&provider, &modules);
+        assert_eq!(best.map(|module| module.handle), Some(worse.handle));
+
+        provider.remove(worse.handle);
+        let modules = provider.get_loaded();
+        assert!(target.pick_best_hook_target(&provider, &modules).is_none());
+    }
+
+    #[test]
+    fn better_match_replaces_worse() {
+        let mut provider = Fake::new();
+        let client = provider.add("C:/Games/hl2/bin/client.dll", Vec::new());
+        let worse = provider.add("C:/Games/mod/bin/server.dll", Vec::new());
+
+        let mut target = Target::default();
+        assert!(!target.should_hook(&provider, &client));
+        assert!(target.should_hook(&provider, &worse));
+        target.hook(&provider, &worse);
+        assert_eq!(target.current_name_index, Some(1));
+
+        // Another module that is just as good doesn't replace the current one.
+        let same = provider.add("C:/Games/other/bin/SERVER.DLL", Vec::new());
+        assert!(!target.should_hook(&provider, &same));
+
+        let better = provider.add("C:/Games/hl2/bin/server.dll", Vec::new());
+        assert!(target.should_hook(&provider, &better));
+        target.hook(&provider, &better);
+        assert_eq!(target.current_name_index, Some(0));
+        assert_eq!(target.handle(), Some(better.handle));
+
+        // Nothing beats the first matcher.
+        let another = provider.add("D:/hl2/bin/server.dll", Vec::new());
+        assert!(!target.should_hook(&provider, &another));
+        assert!(!target.should_hook(&provider, &worse));
+    }
+}
@@ -0,0 +1,96 @@
+use kernel32;
+pub use pattern::FindError;
+use std::ptr;
+use std::ffi::CString;
+use winapi::*;
+
+mod module;
+pub use self::module::ModuleInfo;
+pub mod pe;
+use self::pe::ExportRef;
+pub mod provider;
+pub mod rtti;
+pub mod scan;
+pub use self::scan::Scope;
+pub mod version;
+pub mod windows;
+pub mod xref;
+
+// The lookups that go through the loader of the current process.
+impl ModuleInfo {
+    pub fn get(name: &str) -> Option<ModuleInfo> {
+        windows::WINDOWS.get(name)
+    }
+
+    pub fn get_loaded() -> Vec<ModuleInfo> {
+        windows::WINDOWS.get_loaded()
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<LPVOID> {
+        self.get_export(ExportRef::Name(name))
+    }
+
+    // See ModuleInfo::find_export(). Forwarders that can't be followed are left to the loader.
+    pub fn get_export(&self, export: ExportRef) -> Option<LPVOID> {
+        match self.find_export(&windows::WINDOWS, export) {
+            Some(Ok(address)) => Some(address),
+            Some(Err(forwarder)) => {
+                debug!(target: "moduleinfo",
+                       "Couldn't follow the forwarder {} for {}, asking the loader",
+                       forwarder,
+                       export);
+                self.get_proc_address(export)
+            }
+            None => None,
+        }
+    }
+
+    fn get_proc_address(&self, export: ExportRef) -> Option<LPVOID> {
+        let name;
+        let name_ptr = match export {
+            ExportRef::Name(string) => {
+                name = match CString::new(string) {
+                    Ok(name) => name,
+                    Err(_) => return None,
+                };
+
+                name.as_ptr()
+            }
+            // MAKEINTRESOURCE.
+            ExportRef::Ordinal(ordinal) => ordinal as usize as LPCSTR,
+        };
+
+        unsafe {
+            match kernel32::GetProcAddress(self.handle, name_ptr) {
+                p if p == ptr::null() => None,
+                p => Some(p as LPVOID),
+            }
+        }
+    }
+}
+
+// Lets print_addrs! handle both plain lookups and strict pattern lookups.
+pub trait AddressResult {
+    fn to_address_result(&self) -> Result<LPVOID, FindError>;
+
+    fn address(&self) -> Option<LPVOID> {
+        self.to_address_result().ok()
+    }
+
+    // How the address was found, if there's more than one way.
+    fn method(&self) -> Option<String> {
+        None
+    }
+}
+
+impl AddressResult for Option<LPVOID> {
+    fn to_address_result(&self) -> Result<LPVOID, FindError> {
+        self.ok_or(FindError::NotFound)
+    }
+}
+
+impl AddressResult for Result<LPVOID, FindError> {
+    fn to_address_result(&self) -> Result<LPVOID, FindError> {
+        *self
+    }
+}
@@ -0,0 +1,233 @@
+// A module loaded into memory. Everything here only reads the mapped image, so it works the same
+// on modules of the current process and on fake ones made from byte buffers.
+
+use pattern::{self, FindError};
+use std::{mem, slice};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+use super::pe::{self, ExportRef, ExportTarget, Layout, Pe, Section};
+use super::provider::ModuleProvider;
+use super::rtti;
+use super::scan::{self, Scope};
+use super::version::{self, VersionInfo};
+use super::xref;
+use winapi::{HMODULE, LPVOID};
+
+// Forwarders can chain, but never this deep unless they loop.
+const MAX_FORWARDER_DEPTH: usize = 8;
+
+#[derive(Clone, Copy)]
+pub struct ModuleInfo {
+    pub handle: HMODULE,
+    pub base: LPVOID,
+    pub size: usize,
+}
+
+unsafe impl Send for ModuleInfo {}
+unsafe impl Sync for ModuleInfo {}
+
+impl ModuleInfo {
+    pub fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.base as *const u8, self.size) }
+    }
+
+    pub fn pe<'a>(&'a self) -> Result<Pe<'a>, pe::Error> {
+        Pe::parse(self.bytes(), Layout::Image)
+    }
+
+    pub fn version_info(&self) -> Option<VersionInfo> {
+        self.pe().ok().and_then(|pe| version::version_info(&pe))
+    }
+
+    pub fn sections(&self) -> Vec<Section> {
+        self.pe().map(|pe| pe.sections).unwrap_or_default()
+    }
+
+    // Ranges of offsets from the module base that are covered by the scope.
+    pub fn ranges(&self, scope: Scope) -> Vec<Range<usize>> {
+        scan::ranges(self.bytes(), scope)
+    }
+
+    pub fn find<'a, P>(&self, pattern: P) -> Option<LPVOID>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        self.find_in(Scope::Executable, pattern)
+    }
+
+    pub fn find_all<'a, P>(&self, pattern: P) -> Vec<LPVOID>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        self.find_all_in(Scope::Executable, pattern)
+    }
+
+    // See scan::find_unique_in().
+    pub fn find_unique<'a, P>(&self, pattern: P) -> Result<LPVOID, FindError>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        self.find_unique_in(Scope::Executable, pattern)
+    }
+
+    pub fn find_in<'a, P>(&self, scope: Scope, pattern: P) -> Option<LPVOID>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        scan::find_in(self.bytes(), scope, pattern.into()).map(|rva| self.offset_to_address(rva))
+    }
+
+    pub fn find_all_in<'a, P>(&self, scope: Scope, pattern: P) -> Vec<LPVOID>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        scan::find_all_in(self.bytes(), scope, pattern.into())
+            .into_iter()
+            .map(|rva| self.offset_to_address(rva))
+            .collect()
+    }
+
+    pub fn find_unique_in<'a, P>(&self, scope: Scope, pattern: P) -> Result<LPVOID, FindError>
+        where P: Into<pattern::Pattern<'a>>
+    {
+        scan::find_unique_in(self.bytes(), scope, pattern.into())
+            .map(|rva| self.offset_to_address(rva))
+    }
+
+    // See scan::find_many_in().
+    pub fn find_many<'a, K>(&self,
+                            patterns: &[(K, pattern::Pattern<'a>)])
+                            -> HashMap<K, Vec<LPVOID>>
+        where K: Copy + Eq + Hash
+    {
+        self.find_many_in(Scope::Executable, patterns)
+    }
+
+    pub fn find_many_in<'a, K>(&self,
+                               scope: Scope,
+                               patterns: &[(K, pattern::Pattern<'a>)])
+                               -> HashMap<K, Vec<LPVOID>>
+        where K: Copy + Eq + Hash
+    {
+        scan::find_many_in(self.bytes(), scope, patterns)
+            .into_iter()
+            .map(|(name, rvas)| {
+                (name, rvas.into_iter().map(|rva| self.offset_to_address(rva)).collect())
+            })
+            .collect()
+    }
+
+    // Finds the function that references the string, see xref::find_function_by_string().
+    pub fn find_function_by_string(&self, string: &str) -> Result<LPVOID, FindError> {
+        let pe = try!(self.pe().map_err(|_| FindError::NotFound));
+
+        xref::find_function_by_string(&pe, self.base as usize, string)
+            .map(|rva| self.offset_to_address(rva))
+    }
+
+    // The primary vtable of the class, found through RTTI by its plain or mangled name.
+    pub fn find_vtable(&self, class: &str) -> Option<LPVOID> {
+        self.pe()
+            .ok()
+            .and_then(|pe| rtti::find_primary_vtable(&pe, self.base as usize, class))
+            .map(|rva| self.offset_to_address(rva))
+    }
+
+    // The virtual function at the index in the primary vtable of the class.
+    pub fn find_virtual_function(&self, class: &str, index: usize) -> Option<LPVOID> {
+        self.find_vtable(class).and_then(|vtable| {
+            let entry = vtable as usize + index * mem::size_of::<LPVOID>();
+
+            if entry + mem::size_of::<LPVOID>() > self.base as usize + self.size {
+                return None;
+            }
+
+            Some(unsafe { *(entry as *const LPVOID) })
+        })
+    }
+
+    // Looks the export up in the export directory, following forwarders into the modules of the
+    // provider. A forwarder that can't be followed is returned as the error, e.g. one to an API
+    // set that only the loader can resolve.
+    pub fn find_export(&self,
+                       provider: &ModuleProvider,
+                       export: ExportRef)
+                       -> Option<Result<LPVOID, String>> {
+        self.find_export_at_depth(provider, export, MAX_FORWARDER_DEPTH)
+    }
+
+    fn find_export_at_depth(&self,
+                            provider: &ModuleProvider,
+                            export: ExportRef,
+                            depth: usize)
+                            -> Option<Result<LPVOID, String>> {
+        let target = match self.pe() {
+            Ok(pe) => pe.export(export),
+            Err(_) => return None,
+        };
+
+        match target {
+            Some(ExportTarget::Rva(rva)) => Some(Ok(self.offset_to_address(rva))),
+            Some(ExportTarget::Forwarder(forwarder)) => {
+                let resolved = match pe::parse_forwarder(&forwarder) {
+                    Some((module, forwarded)) if depth > 0 => {
+                        provider.get(&format!("{}.dll", module)).and_then(|module_info| {
+                            module_info.find_export_at_depth(provider, forwarded, depth - 1)
+                        })
+                    }
+                    _ => None,
+                };
+
+                Some(resolved.unwrap_or(Err(forwarder)))
+            }
+            None => None,
+        }
+    }
+
+    pub fn offset_to_address(&self, offset: usize) -> LPVOID {
+        (self.base as usize + offset) as LPVOID
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::pe::builder::*;
+    use super::super::provider::Fake;
+
+    #[test]
+    fn find_export_follows_forwarders() {
+        let mut fake = Fake::new();
+
+        let exports = export_directory(0x2000,
+                                       1,
+                                       &[TestExport::Rva(0x1000),
+                                         TestExport::Forwarder("B.Target"),
+                                         TestExport::Forwarder("B.#3"),
+                                         TestExport::Forwarder("B.Missing"),
+                                         TestExport::Forwarder("api-ms-win-core-heap-l1-1-0.X"),
+                                         TestExport::Forwarder("A.Loop")],
+                                       &[("Direct", 0),
+                                         ("ByName", 1),
+                                         ("ByOrdinal", 2),
+                                         ("Missing", 3),
+                                         ("ApiSet", 4),
+                                         ("Loop", 5)]);
+        let a = fake.add("C:/bin/a.dll", exporting_image(&[0xC3], &exports));
+
+        let exports = export_directory(0x2000,
+                                       3,
+                                       &[TestExport::Rva(0x1020)],
+                                       &[("Target", 0)]);
+        let b = fake.add("C:/bin/B.DLL", exporting_image(&[0xC3], &exports));
+
+        let find = |name| fake.get("a.dll").unwrap().find_export(&fake, ExportRef::Name(name));
+
+        assert_eq!(find("Direct"), Some(Ok(a.offset_to_address(0x1000))));
+        assert_eq!(find("ByName"), Some(Ok(b.offset_to_address(0x1020))));
+        assert_eq!(find("ByOrdinal"), Some(Ok(b.offset_to_address(0x1020))));
+        assert_eq!(find("NotExported"), None);
+
+        // The forwarders that can't be followed are returned for the loader.
+        assert_eq!(find("Missing"), Some(Err("B.Missing".to_owned())));
+        assert_eq!(find("ApiSet"), Some(Err("api-ms-win-core-heap-l1-1-0.X".to_owned())));
+        // A loop stops at the depth limit.
+        assert_eq!(find("Loop"), Some(Err("A.Loop".to_owned())));
+    }
+}
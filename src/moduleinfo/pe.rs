@@ -0,0 +1,1207 @@
+// A minimal PE parser. It only reads from a byte slice, so it works the same on a module mapped
+// into memory by the loader and on a DLL file read from disk.
+
+use std::{cmp, error, fmt};
+use std::ops::Range;
+
+const IMAGE_DOS_SIGNATURE: u16 = 0x5A4D;
+const IMAGE_NT_SIGNATURE: u32 = 0x00004550;
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10B;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20B;
+const IMAGE_SIZEOF_FILE_HEADER: usize = 20;
+const IMAGE_SIZEOF_SECTION_HEADER: usize = 40;
+const IMAGE_NUMBEROF_DIRECTORY_ENTRIES: usize = 16;
+
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x00000020;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x20000000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x40000000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x80000000;
+
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+const IMAGE_REL_BASED_HIGH: u16 = 1;
+const IMAGE_REL_BASED_LOW: u16 = 2;
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+// Well past any 32-bit game module, and small enough to allocate when mapping a file.
+const MAX_IMAGE_SIZE: u32 = 0x2000_0000;
+
+const IMAGE_SIZEOF_IMPORT_DESCRIPTOR: usize = 20;
+// A corrupted directory could go on until the end of the image.
+const MAX_IMPORTS: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    // Sections are at their PointerToRawData offsets, as in a file on disk.
+    File,
+    // Sections are at their RVAs, as in a module mapped by the loader.
+    Image,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Truncated,
+    BadDosSignature,
+    BadNtSignature,
+    BadOptionalHeaderMagic(u16),
+    // SizeOfImage is past the cap or past the end of the last section.
+    BadImageSize(u32),
+    // The section, by index, ends past SizeOfImage.
+    SectionOutOfRange(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Truncated => write!(f, "the PE headers are truncated"),
+            Error::BadDosSignature => write!(f, "invalid DOS signature"),
+            Error::BadNtSignature => write!(f, "invalid NT signature"),
+            Error::BadOptionalHeaderMagic(magic) => {
+                write!(f, "unknown optional header magic 0x{:X}", magic)
+            }
+            Error::BadImageSize(size) => write!(f, "invalid image size 0x{:X}", size),
+            Error::SectionOutOfRange(index) => {
+                write!(f, "section {} ends past the end of the image", index)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Truncated => "the PE headers are truncated",
+            Error::BadDosSignature => "invalid DOS signature",
+            Error::BadNtSignature => "invalid NT signature",
+            Error::BadOptionalHeaderMagic(_) => "unknown optional header magic",
+            Error::BadImageSize(_) => "invalid image size",
+            Error::SectionOutOfRange(_) => "section ends past the end of the image",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub raw_offset: u32,
+    pub raw_size: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & (IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_CNT_CODE) != 0
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_READ != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_WRITE != 0
+    }
+
+    // Some linkers leave VirtualSize at zero.
+    pub fn mapped_size(&self) -> u32 {
+        if self.virtual_size != 0 {
+            self.virtual_size
+        } else {
+            self.raw_size
+        }
+    }
+
+    // The range of RVAs covered by the section.
+    pub fn rva_range(&self) -> Range<usize> {
+        let start = self.virtual_address as usize;
+        start..start.saturating_add(self.mapped_size() as usize)
+    }
+
+    pub fn contains_rva(&self, rva: usize) -> bool {
+        let range = self.rva_range();
+        rva >= range.start && rva < range.end
+    }
+}
+
+// A location the loader patches when the module isn't loaded at its preferred base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub rva: usize,
+    pub size: usize,
+}
+
+// An export, by name or by ordinal. Ordinals include the ordinal base, like GetProcAddress() takes
+// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportRef<'a> {
+    Name(&'a str),
+    Ordinal(u16),
+}
+
+impl<'a> fmt::Display for ExportRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportRef::Name(name) => write!(f, "{}", name),
+            ExportRef::Ordinal(ordinal) => write!(f, "#{}", ordinal),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    Rva(usize),
+    // The export is implemented in another module, e.g. "NTDLL.RtlAllocateHeap" or "NTDLL.#12".
+    Forwarder(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: Option<String>,
+    pub ordinal: u16,
+    pub target: ExportTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    // The module as named in the import directory, like "KERNEL32.dll".
+    pub module: String,
+    // None for imports by ordinal.
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    // The RVA of the import address table entry, which the loader fills with the address.
+    pub iat_rva: usize,
+}
+
+// Splits a forwarder into the module name, without the extension, and the export in that module.
+pub fn parse_forwarder<'a>(forwarder: &'a str) -> Option<(&'a str, ExportRef<'a>)> {
+    let dot = match forwarder.find('.') {
+        Some(dot) if dot > 0 && dot + 1 < forwarder.len() => dot,
+        _ => return None,
+    };
+
+    let module = &forwarder[..dot];
+    let export = &forwarder[dot + 1..];
+
+    if export.starts_with('#') {
+        export[1..].parse().ok().map(|ordinal| (module, ExportRef::Ordinal(ordinal)))
+    } else {
+        Some((module, ExportRef::Name(export)))
+    }
+}
+
+// The fields of IMAGE_EXPORT_DIRECTORY that are needed for the lookups.
+struct ExportDirectory {
+    range: Range<usize>,
+    ordinal_base: u32,
+    function_count: usize,
+    name_count: usize,
+    functions: usize,
+    names: usize,
+    name_ordinals: usize,
+}
+
+pub struct Pe<'a> {
+    data: &'a [u8],
+    layout: Layout,
+    pub is_64bit: bool,
+    pub machine: u16,
+    pub timestamp: u32,
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<Section>,
+}
+
+impl<'a> Pe<'a> {
+    pub fn parse(data: &'a [u8], layout: Layout) -> Result<Pe<'a>, Error> {
+        if try!(read_u16(data, 0)) != IMAGE_DOS_SIGNATURE {
+            return Err(Error::BadDosSignature);
+        }
+
+        let nt = try!(read_u32(data, 0x3C)) as usize;
+        if try!(read_u32(data, nt)) != IMAGE_NT_SIGNATURE {
+            return Err(Error::BadNtSignature);
+        }
+
+        let file_header = nt + 4;
+        let machine = try!(read_u16(data, file_header));
+        let section_count = try!(read_u16(data, file_header + 2)) as usize;
+        let timestamp = try!(read_u32(data, file_header + 4));
+        let optional_header_size = try!(read_u16(data, file_header + 16)) as usize;
+
+        let optional_header = file_header + IMAGE_SIZEOF_FILE_HEADER;
+        let magic = try!(read_u16(data, optional_header));
+
+        let (is_64bit, image_base, directories_offset) = match magic {
+            IMAGE_NT_OPTIONAL_HDR32_MAGIC => {
+                (false, try!(read_u32(data, optional_header + 28)) as u64, optional_header + 92)
+            }
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => {
+                (true, try!(read_u64(data, optional_header + 24)), optional_header + 108)
+            }
+            _ => return Err(Error::BadOptionalHeaderMagic(magic)),
+        };
+
+        let section_alignment = try!(read_u32(data, optional_header + 32));
+        let size_of_image = try!(read_u32(data, optional_header + 56));
+        let size_of_headers = try!(read_u32(data, optional_header + 60));
+
+        // The count comes from the file, so it can't be more than the optional header has room for.
+        let directories_size = (optional_header + optional_header_size)
+            .saturating_sub(directories_offset + 4);
+        let directory_count = cmp::min(try!(read_u32(data, directories_offset)) as usize,
+                                       cmp::min(IMAGE_NUMBEROF_DIRECTORY_ENTRIES,
+                                                directories_size / 8));
+        let mut data_directories = Vec::with_capacity(directory_count);
+        for i in 0..directory_count {
+            let offset = directories_offset + 4 + i * 8;
+
+            data_directories.push(DataDirectory {
+                virtual_address: try!(read_u32(data, offset)),
+                size: try!(read_u32(data, offset + 4)),
+            });
+        }
+
+        let section_headers = optional_header + optional_header_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let offset = section_headers + i * IMAGE_SIZEOF_SECTION_HEADER;
+            let name = try!(read_bytes(data, offset, 8));
+            let name_len = name.iter().position(|&c| c == 0).unwrap_or(8);
+
+            sections.push(Section {
+                name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                virtual_size: try!(read_u32(data, offset + 8)),
+                virtual_address: try!(read_u32(data, offset + 12)),
+                raw_size: try!(read_u32(data, offset + 16)),
+                raw_offset: try!(read_u32(data, offset + 20)),
+                characteristics: try!(read_u32(data, offset + 36)),
+            });
+        }
+
+        Ok(Pe {
+            data: data,
+            layout: layout,
+            is_64bit: is_64bit,
+            machine: machine,
+            timestamp: timestamp,
+            image_base: image_base,
+            section_alignment: section_alignment,
+            size_of_image: size_of_image,
+            size_of_headers: size_of_headers,
+            data_directories: data_directories,
+            sections: sections,
+        })
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn section_for_rva(&self, rva: usize) -> Option<&Section> {
+        self.sections.iter().find(|section| section.contains_rva(rva))
+    }
+
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        match self.data_directories.get(index) {
+            Some(&directory) if directory.virtual_address != 0 && directory.size != 0 => {
+                Some(directory)
+            }
+            _ => None,
+        }
+    }
+
+    // Translates an RVA into an offset into the underlying data.
+    pub fn rva_to_offset(&self, rva: usize) -> Option<usize> {
+        match self.layout {
+            Layout::Image => Some(rva),
+            Layout::File => {
+                if rva < self.size_of_headers as usize {
+                    return Some(rva);
+                }
+
+                self.section_for_rva(rva).and_then(|section| {
+                    let delta = rva - section.virtual_address as usize;
+
+                    // The tail of the section past the raw data is zero-filled by the loader and
+                    // doesn't exist in the file.
+                    if delta < section.raw_size as usize {
+                        Some(section.raw_offset as usize + delta)
+                    } else {
+                        None
+                    }
+                })
+            }
+        }
+    }
+
+    // The data at the section's RVAs. In the file layout, this is only the raw data.
+    pub fn section_data(&self, section: &Section) -> &'a [u8] {
+        let (start, len) = match self.layout {
+            Layout::Image => (section.virtual_address as usize, section.mapped_size() as usize),
+            Layout::File => (section.raw_offset as usize, section.raw_size as usize),
+        };
+
+        let start = if start > self.data.len() { self.data.len() } else { start };
+        let end = if len > self.data.len() - start { self.data.len() } else { start + len };
+
+        &self.data[start..end]
+    }
+
+    pub fn read(&self, rva: usize, len: usize) -> Option<&'a [u8]> {
+        self.rva_to_offset(rva).and_then(|offset| read_bytes(self.data, offset, len).ok())
+    }
+
+    pub fn read_u16(&self, rva: usize) -> Option<u16> {
+        self.rva_to_offset(rva).and_then(|offset| read_u16(self.data, offset).ok())
+    }
+
+    pub fn read_u32(&self, rva: usize) -> Option<u32> {
+        self.rva_to_offset(rva).and_then(|offset| read_u32(self.data, offset).ok())
+    }
+
+    // The base relocations, in the order they appear in the table. A missing or malformed table
+    // gives whatever could be read before the problem.
+    pub fn relocations(&self) -> Vec<Relocation> {
+        let mut rv = Vec::new();
+
+        let directory = match self.data_directory(IMAGE_DIRECTORY_ENTRY_BASERELOC) {
+            Some(directory) => directory,
+            None => return rv,
+        };
+
+        let mut block = directory.virtual_address as usize;
+        let end = block + directory.size as usize;
+
+        while block + 8 <= end {
+            let (page, block_size) = match (self.read_u32(block), self.read_u32(block + 4)) {
+                (Some(page), Some(block_size)) => (page as usize, block_size as usize),
+                _ => break,
+            };
+
+            if block_size < 8 || block + block_size > end {
+                break;
+            }
+
+            for i in 0..(block_size - 8) / 2 {
+                let entry = match self.read_u16(block + 8 + i * 2) {
+                    Some(entry) => entry,
+                    None => break,
+                };
+
+                let size = match entry >> 12 {
+                    IMAGE_REL_BASED_ABSOLUTE => continue,
+                    IMAGE_REL_BASED_HIGH | IMAGE_REL_BASED_LOW => 2,
+                    IMAGE_REL_BASED_HIGHLOW => 4,
+                    IMAGE_REL_BASED_DIR64 => 8,
+                    _ => continue,
+                };
+
+                rv.push(Relocation {
+                    rva: page + (entry & 0xFFF) as usize,
+                    size: size,
+                });
+            }
+
+            block += block_size;
+        }
+
+        rv
+    }
+
+    // Every export, in the order of the ordinals. Unused slots in the address table are skipped.
+    pub fn exports(&self) -> Vec<Export> {
+        let directory = match self.export_directory() {
+            Some(directory) => directory,
+            None => return Vec::new(),
+        };
+
+        let mut names = vec![None; directory.function_count];
+        for i in 0..directory.name_count {
+            let index = match self.read_u16(directory.name_ordinals + i * 2) {
+                Some(index) => index as usize,
+                None => break,
+            };
+
+            if index < names.len() {
+                names[index] = self.export_name(&directory, i);
+            }
+        }
+
+        let mut rv = Vec::new();
+
+        for (index, name) in names.into_iter().enumerate() {
+            if let Some(target) = self.export_target(&directory, index) {
+                rv.push(Export {
+                    name: name,
+                    ordinal: (directory.ordinal_base as usize + index) as u16,
+                    target: target,
+                });
+            }
+        }
+
+        rv
+    }
+
+    pub fn export(&self, export: ExportRef) -> Option<ExportTarget> {
+        let directory = match self.export_directory() {
+            Some(directory) => directory,
+            None => return None,
+        };
+
+        let index = match export {
+            ExportRef::Ordinal(ordinal) => {
+                match (ordinal as u32).checked_sub(directory.ordinal_base) {
+                    Some(index) => index as usize,
+                    None => return None,
+                }
+            }
+            ExportRef::Name(name) => {
+                let position = (0..directory.name_count).find(|&i| {
+                    self.export_name(&directory, i).as_ref().map(|n| &n[..]) == Some(name)
+                });
+
+                match position.and_then(|i| self.read_u16(directory.name_ordinals + i * 2)) {
+                    Some(index) => index as usize,
+                    None => return None,
+                }
+            }
+        };
+
+        self.export_target(&directory, index)
+    }
+
+    fn export_directory(&self) -> Option<ExportDirectory> {
+        let directory = match self.data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT) {
+            Some(directory) => directory,
+            None => return None,
+        };
+
+        let start = directory.virtual_address as usize;
+        let read = |offset| self.read_u32(start + offset);
+
+        match (read(16), read(20), read(24), read(28), read(32), read(36)) {
+            (Some(ordinal_base),
+             Some(function_count),
+             Some(name_count),
+             Some(functions),
+             Some(names),
+             Some(name_ordinals)) => {
+                // The tables can't be bigger than the image, anything else is garbage.
+                let max_count = self.size_of_image / 4;
+                if function_count > max_count || name_count > max_count {
+                    return None;
+                }
+
+                Some(ExportDirectory {
+                    range: start..start + directory.size as usize,
+                    ordinal_base: ordinal_base,
+                    function_count: function_count as usize,
+                    name_count: name_count as usize,
+                    functions: functions as usize,
+                    names: names as usize,
+                    name_ordinals: name_ordinals as usize,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn export_name(&self, directory: &ExportDirectory, i: usize) -> Option<String> {
+        self.read_u32(directory.names + i * 4)
+            .and_then(|rva| self.read_cstr(rva as usize))
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    }
+
+    fn export_target(&self, directory: &ExportDirectory, index: usize) -> Option<ExportTarget> {
+        if index >= directory.function_count {
+            return None;
+        }
+
+        let rva = match self.read_u32(directory.functions + index * 4) {
+            Some(0) | None => return None,
+            Some(rva) => rva as usize,
+        };
+
+        // Forwarders point at a string inside the export directory instead of at code.
+        if rva >= directory.range.start && rva < directory.range.end {
+            self.read_cstr(rva).map(|forwarder| {
+                ExportTarget::Forwarder(String::from_utf8_lossy(forwarder).into_owned())
+            })
+        } else {
+            Some(ExportTarget::Rva(rva))
+        }
+    }
+
+    // Every import, in the order of the import directory. The names come from the import lookup
+    // tables, since the loader overwrites the import address tables with the addresses.
+    pub fn imports(&self) -> Vec<Import> {
+        let mut rv = Vec::new();
+
+        let directory = match self.data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT) {
+            Some(directory) => directory.virtual_address as usize,
+            None => return rv,
+        };
+
+        let thunk_size = if self.is_64bit { 8 } else { 4 };
+        let ordinal_flag = if self.is_64bit { 1 << 63 } else { 1 << 31 };
+
+        for descriptor in (0..).map(|i| directory + i * IMAGE_SIZEOF_IMPORT_DESCRIPTOR) {
+            let (lookup, name, iat) = match (self.read_u32(descriptor),
+                                             self.read_u32(descriptor + 12),
+                                             self.read_u32(descriptor + 16)) {
+                (Some(lookup), Some(name), Some(iat)) if name != 0 && iat != 0 => {
+                    (lookup as usize, name as usize, iat as usize)
+                }
+                _ => break,
+            };
+
+            let module = match self.read_cstr(name) {
+                Some(module) => String::from_utf8_lossy(module).into_owned(),
+                None => break,
+            };
+
+            // Without the lookup table, only a file still has the names in the address table.
+            let lookup = match (lookup, self.layout) {
+                (0, Layout::File) => iat,
+                (0, Layout::Image) => continue,
+                (lookup, _) => lookup,
+            };
+
+            for i in 0.. {
+                if rv.len() >= MAX_IMPORTS {
+                    return rv;
+                }
+
+                let thunk = match self.read(lookup + i * thunk_size, thunk_size) {
+                    Some(data) if self.is_64bit => read_u64(data, 0).unwrap_or(0),
+                    Some(data) => read_u32(data, 0).unwrap_or(0) as u64,
+                    None => 0,
+                };
+
+                if thunk == 0 {
+                    break;
+                }
+
+                let (name, ordinal) = if thunk & ordinal_flag != 0 {
+                    (None, Some(thunk as u16))
+                } else {
+                    // IMAGE_IMPORT_BY_NAME starts with the hint.
+                    let name = self.read_cstr(thunk as usize + 2)
+                                   .map(|name| String::from_utf8_lossy(name).into_owned());
+                    (name, None)
+                };
+
+                rv.push(Import {
+                    module: module.clone(),
+                    name: name,
+                    ordinal: ordinal,
+                    iat_rva: iat + i * thunk_size,
+                });
+            }
+        }
+
+        rv
+    }
+
+    // Reads a NUL-terminated string.
+    pub fn read_cstr(&self, rva: usize) -> Option<&'a [u8]> {
+        self.rva_to_offset(rva).and_then(|offset| {
+            if offset >= self.data.len() {
+                return None;
+            }
+
+            let rest = &self.data[offset..];
+            rest.iter().position(|&c| c == 0).map(|len| &rest[..len])
+        })
+    }
+}
+
+// Lays out a PE file the way the loader would map it, minus relocations and imports. The result
+// can be parsed with Layout::Image.
+pub fn map_image(file: &[u8]) -> Result<Vec<u8>, Error> {
+    let pe = try!(Pe::parse(file, Layout::File));
+    try!(check_image_size(&pe));
+
+    let mut image = vec![0u8; pe.size_of_image as usize];
+
+    let headers = try!(read_bytes(file, 0, cmp::min(pe.size_of_headers as usize, file.len())));
+    let len = cmp::min(headers.len(), image.len());
+    image[..len].copy_from_slice(&headers[..len]);
+
+    for section in &pe.sections {
+        let data = pe.section_data(section);
+        let len = cmp::min(data.len(), section.mapped_size() as usize);
+
+        let start = section.virtual_address as usize;
+        if start >= image.len() {
+            continue;
+        }
+
+        let len = cmp::min(len, image.len() - start);
+        image[start..start + len].copy_from_slice(&data[..len]);
+    }
+
+    Ok(image)
+}
+
+// Checks SizeOfImage before it's allocated: it has to fit every section and can't go past the
+// last one by more than the section alignment.
+fn check_image_size(pe: &Pe) -> Result<(), Error> {
+    let size = pe.size_of_image as u64;
+    if size > MAX_IMAGE_SIZE as u64 {
+        return Err(Error::BadImageSize(pe.size_of_image));
+    }
+
+    let mut end = pe.size_of_headers as u64;
+
+    for (index, section) in pe.sections.iter().enumerate() {
+        let section_end = section.virtual_address as u64 + section.mapped_size() as u64;
+        if section_end > size {
+            return Err(Error::SectionOutOfRange(index));
+        }
+
+        end = cmp::max(end, section_end);
+    }
+
+    let alignment = cmp::max(pe.section_alignment as u64, 1);
+    if size > (end + alignment - 1) / alignment * alignment {
+        return Err(Error::BadImageSize(pe.size_of_image));
+    }
+
+    Ok(())
+}
+
+fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    if offset > data.len() || len > data.len() - offset {
+        Err(Error::Truncated)
+    } else {
+        Ok(&data[offset..offset + len])
+    }
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    read_bytes(data, offset, 2).map(|b| b[0] as u16 | (b[1] as u16) << 8)
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    read_bytes(data, offset, 4).map(|b| {
+        b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+    })
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    read_u32(data, offset).and_then(|low| {
+        read_u32(data, offset + 4).map(|high| low as u64 | (high as u64) << 32)
+    })
+}
+
+// Builds small PE files for the tests of everything that reads them.
+#[cfg(test)]
+pub mod builder {
+    use super::*;
+
+    pub const NT: usize = 0x40;
+    pub const OPTIONAL_HEADER: usize = NT + 4 + IMAGE_SIZEOF_FILE_HEADER;
+    pub const OPTIONAL_HEADER_SIZE: usize = 224;
+    pub const SECTION_HEADERS: usize = OPTIONAL_HEADER + OPTIONAL_HEADER_SIZE;
+    pub const HEADERS_SIZE: usize = 0x200;
+    pub const IMAGE_BASE: u32 = 0x1000_0000;
+
+    pub const CODE: u32 = IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ;
+
+    pub struct TestSection<'a> {
+        pub name: &'static str,
+        pub virtual_address: u32,
+        pub virtual_size: u32,
+        pub raw_offset: u32,
+        pub data: &'a [u8],
+        pub characteristics: u32,
+    }
+
+    pub const TEXT: TestSection<'static> = TestSection {
+        name: ".text",
+        virtual_address: 0x1000,
+        virtual_size: 0x10,
+        raw_offset: 0x200,
+        data: &[0x55, 0x8B, 0xEC, 0x5D, 0xC3],
+        characteristics: CODE,
+    };
+
+    pub const DATA: TestSection<'static> = TestSection {
+        name: ".data",
+        virtual_address: 0x2000,
+        virtual_size: 0x800,
+        raw_offset: 0x400,
+        data: &[1, 2, 3, 4],
+        characteristics: IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+    };
+
+    pub fn put(file: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+        if file.len() < offset + bytes.len() {
+            file.resize(offset + bytes.len(), 0);
+        }
+
+        file[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn put_u16(file: &mut Vec<u8>, offset: usize, value: u16) {
+        put(file, offset, &[value as u8, (value >> 8) as u8]);
+    }
+
+    pub fn put_u32(file: &mut Vec<u8>, offset: usize, value: u32) {
+        put_u16(file, offset, value as u16);
+        put_u16(file, offset + 2, (value >> 16) as u16);
+    }
+
+    // A 32-bit PE file with the sections, and the raw data of each at its offset.
+    pub fn build(sections: &[TestSection], size_of_image: u32) -> Vec<u8> {
+        let mut file = vec![0; HEADERS_SIZE];
+
+        put_u16(&mut file, 0, IMAGE_DOS_SIGNATURE);
+        put_u32(&mut file, 0x3C, NT as u32);
+        put_u32(&mut file, NT, IMAGE_NT_SIGNATURE);
+        put_u16(&mut file, NT + 4, 0x14C);
+        put_u16(&mut file, NT + 6, sections.len() as u16);
+        put_u32(&mut file, NT + 8, 0x5000_0000);
+        put_u16(&mut file, NT + 20, OPTIONAL_HEADER_SIZE as u16);
+
+        put_u16(&mut file, OPTIONAL_HEADER, IMAGE_NT_OPTIONAL_HDR32_MAGIC);
+        put_u32(&mut file, OPTIONAL_HEADER + 28, IMAGE_BASE);
+        put_u32(&mut file, OPTIONAL_HEADER + 32, 0x1000);
+        put_u32(&mut file, OPTIONAL_HEADER + 36, 0x200);
+        put_u32(&mut file, OPTIONAL_HEADER + 56, size_of_image);
+        put_u32(&mut file, OPTIONAL_HEADER + 60, HEADERS_SIZE as u32);
+        put_u32(&mut file, OPTIONAL_HEADER + 92, 16);
+
+        for (i, section) in sections.iter().enumerate() {
+            let header = SECTION_HEADERS + i * IMAGE_SIZEOF_SECTION_HEADER;
+            let raw_size = (section.data.len() as u32 + 0x1FF) & !0x1FF;
+
+            put(&mut file, header, section.name.as_bytes());
+            put_u32(&mut file, header + 8, section.virtual_size);
+            put_u32(&mut file, header + 12, section.virtual_address);
+            put_u32(&mut file, header + 16, raw_size);
+            put_u32(&mut file, header + 20, section.raw_offset);
+            put_u32(&mut file, header + 36, section.characteristics);
+
+            put(&mut file, section.raw_offset as usize, section.data);
+            let end = (section.raw_offset + raw_size) as usize;
+            if file.len() < end {
+                file.resize(end, 0);
+            }
+        }
+
+        file
+    }
+
+    pub fn set_directory(file: &mut Vec<u8>, index: usize, virtual_address: u32, size: u32) {
+        put_u32(file, OPTIONAL_HEADER + 96 + index * 8, virtual_address);
+        put_u32(file, OPTIONAL_HEADER + 100 + index * 8, size);
+    }
+
+    pub enum TestExport {
+        Rva(u32),
+        Forwarder(&'static str),
+    }
+
+    // Appends the NUL-terminated string, returns its offset.
+    pub fn put_cstr(data: &mut Vec<u8>, string: &str) -> usize {
+        let offset = data.len();
+        data.extend_from_slice(string.as_bytes());
+        data.push(0);
+        offset
+    }
+
+    // An export directory that starts at the RVA. The names refer to the functions by index.
+    pub fn export_directory(rva: u32,
+                            ordinal_base: u32,
+                            functions: &[TestExport],
+                            names: &[(&str, u16)])
+                            -> Vec<u8> {
+        let names_offset = 40 + functions.len() * 4;
+        let ordinals_offset = names_offset + names.len() * 4;
+        let mut data = vec![0; ordinals_offset + names.len() * 2];
+
+        put_u32(&mut data, 16, ordinal_base);
+        put_u32(&mut data, 20, functions.len() as u32);
+        put_u32(&mut data, 24, names.len() as u32);
+        put_u32(&mut data, 28, rva + 40);
+        put_u32(&mut data, 32, rva + names_offset as u32);
+        put_u32(&mut data, 36, rva + ordinals_offset as u32);
+
+        for (i, function) in functions.iter().enumerate() {
+            let target = match *function {
+                TestExport::Rva(target) => target,
+                TestExport::Forwarder(forwarder) => rva + put_cstr(&mut data, forwarder) as u32,
+            };
+
+            put_u32(&mut data, 40 + i * 4, target);
+        }
+
+        for (i, &(name, index)) in names.iter().enumerate() {
+            let name = rva + put_cstr(&mut data, name) as u32;
+            put_u32(&mut data, names_offset + i * 4, name);
+            put_u16(&mut data, ordinals_offset + i * 2, index);
+        }
+
+        data
+    }
+
+    pub enum TestImport {
+        Name(&'static str),
+        Ordinal(u16),
+    }
+
+    // The address that the loader put in the address table entry, for telling them apart.
+    pub fn imported_address(module: usize, index: usize) -> u32 {
+        0x7000_0000 + module as u32 * 0x100 + index as u32 * 4
+    }
+
+    // An import directory that starts at the RVA, with the lookup and address tables of every
+    // module after it. The address tables are filled in like the loader does, see
+    // imported_address().
+    pub fn import_directory(rva: u32, modules: &[(&str, &[TestImport])]) -> Vec<u8> {
+        let mut data = vec![0; (modules.len() + 1) * IMAGE_SIZEOF_IMPORT_DESCRIPTOR];
+
+        for (i, &(module, imports)) in modules.iter().enumerate() {
+            let descriptor = i * IMAGE_SIZEOF_IMPORT_DESCRIPTOR;
+
+            let lookup = data.len();
+            let iat = lookup + (imports.len() + 1) * 4;
+            data.resize(iat + (imports.len() + 1) * 4, 0);
+
+            put_u32(&mut data, descriptor, rva + lookup as u32);
+            put_u32(&mut data, descriptor + 16, rva + iat as u32);
+            let name = rva + put_cstr(&mut data, module) as u32;
+            put_u32(&mut data, descriptor + 12, name);
+
+            for (j, import) in imports.iter().enumerate() {
+                let thunk = match *import {
+                    TestImport::Name(name) => {
+                        // IMAGE_IMPORT_BY_NAME starts with the hint.
+                        let hint = data.len();
+                        data.extend_from_slice(&[0, 0]);
+                        put_cstr(&mut data, name);
+                        rva + hint as u32
+                    }
+                    TestImport::Ordinal(ordinal) => 1 << 31 | ordinal as u32,
+                };
+
+                put_u32(&mut data, lookup + j * 4, thunk);
+                put_u32(&mut data, iat + j * 4, imported_address(i, j));
+            }
+        }
+
+        data
+    }
+
+    // An image with the code and an import directory at 0x2000.
+    pub fn importing_image(code: &[u8], imports: &[u8]) -> Vec<u8> {
+        let mut image = build_image(&[code, imports]);
+        set_directory(&mut image, IMAGE_DIRECTORY_ENTRY_IMPORT, 0x2000, imports.len() as u32);
+        image
+    }
+
+    // An image with the code and an export directory at 0x2000.
+    pub fn exporting_image(code: &[u8], exports: &[u8]) -> Vec<u8> {
+        let mut image = build_image(&[code, exports]);
+        set_directory(&mut image, IMAGE_DIRECTORY_ENTRY_EXPORT, 0x2000, exports.len() as u32);
+        image
+    }
+
+    // A mapped image with a section per piece of data: the first one is code at 0x1000, the
+    // others are data every 0x1000 after it. Only .data, the third one, is writable.
+    pub fn build_image(sections: &[&[u8]]) -> Vec<u8> {
+        let names = [".text", ".rdata", ".data", ".reloc"];
+        let sections = sections.iter()
+                               .enumerate()
+                               .map(|(i, data)| {
+                                   TestSection {
+                                       name: names[i],
+                                       virtual_address: 0x1000 * (i as u32 + 1),
+                                       virtual_size: 0x1000,
+                                       raw_offset: 0x200 + 0x1000 * i as u32,
+                                       data: data,
+                                       characteristics: match i {
+                                           0 => CODE,
+                                           2 => IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+                                           _ => IMAGE_SCN_MEM_READ,
+                                       },
+                                   }
+                               })
+                               .collect::<Vec<_>>();
+
+        map_image(&build(&sections, 0x1000 * (sections.len() as u32 + 1))).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::builder::*;
+
+    #[test]
+    fn parse_file_layout() {
+        let file = build(&[TEXT, DATA], 0x3000);
+        let pe = Pe::parse(&file, Layout::File).unwrap();
+
+        assert!(!pe.is_64bit);
+        assert_eq!(pe.machine, 0x14C);
+        assert_eq!(pe.timestamp, 0x5000_0000);
+        assert_eq!(pe.image_base, 0x1000_0000);
+        assert_eq!(pe.size_of_image, 0x3000);
+        assert_eq!(pe.data_directories.len(), 16);
+
+        let names = pe.sections.iter().map(|section| &section.name[..]).collect::<Vec<_>>();
+        assert_eq!(names, vec![".text", ".data"]);
+
+        let text = pe.section(".text").unwrap();
+        assert!(text.is_executable());
+        assert!(!pe.section(".data").unwrap().is_executable());
+        assert_eq!(text.raw_size, 0x200);
+
+        assert_eq!(pe.rva_to_offset(0x1003), Some(0x203));
+        assert_eq!(pe.rva_to_offset(0x2003), Some(0x403));
+        // Past the raw data of .data, only zero-filled by the loader.
+        assert_eq!(pe.rva_to_offset(0x2600), None);
+        assert_eq!(pe.read(0x1000, 5), Some(TEXT.data));
+        assert_eq!(&pe.section_data(text)[..5], TEXT.data);
+    }
+
+    #[test]
+    fn parse_image_layout() {
+        let image = map_image(&build(&[TEXT, DATA], 0x3000)).unwrap();
+        assert_eq!(image.len(), 0x3000);
+
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+        assert_eq!(pe.sections.len(), 2);
+
+        assert_eq!(pe.rva_to_offset(0x2600), Some(0x2600));
+        assert_eq!(pe.read(0x1000, 5), Some(TEXT.data));
+        assert_eq!(pe.read_u32(0x2000), Some(0x04030201));
+
+        let text = pe.section(".text").unwrap();
+        assert_eq!(pe.section_data(text).len(), 0x10);
+        assert_eq!(&pe.section_data(text)[..5], TEXT.data);
+        assert!(pe.section_data(text)[5..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn parse_truncated_headers() {
+        let file = build(&[TEXT, DATA], 0x3000);
+
+        for &len in &[0, 1, 0x3C, NT + 2, OPTIONAL_HEADER + 1, OPTIONAL_HEADER + 60,
+                      SECTION_HEADERS + IMAGE_SIZEOF_SECTION_HEADER + 10] {
+            assert_eq!(Pe::parse(&file[..len], Layout::File).err(), Some(Error::Truncated),
+                       "{}", len);
+        }
+
+        // e_lfanew past the end of the file.
+        let mut file = file;
+        put_u32(&mut file, 0x3C, 0x10000);
+        assert_eq!(Pe::parse(&file, Layout::File).err(), Some(Error::Truncated));
+    }
+
+    #[test]
+    fn parse_directory_count() {
+        let mut file = build(&[TEXT], 0x2000);
+        put_u32(&mut file, OPTIONAL_HEADER + 92, 0xFFFF_FFFF);
+        assert_eq!(Pe::parse(&file, Layout::File).unwrap().data_directories.len(), 16);
+
+        // Room for three directories in the optional header.
+        let mut file = build(&[], 0x1000);
+        put_u16(&mut file, NT + 20, 96 + 4 + 3 * 8);
+        assert_eq!(Pe::parse(&file, Layout::File).unwrap().data_directories.len(), 3);
+
+        // No room for the count either.
+        put_u16(&mut file, NT + 20, 90);
+        assert_eq!(Pe::parse(&file, Layout::File).unwrap().data_directories.len(), 0);
+    }
+
+    fn exporting() -> Vec<u8> {
+        let exports = export_directory(0x2000,
+                                       5,
+                                       &[TestExport::Rva(0x1000),
+                                         TestExport::Rva(0),
+                                         TestExport::Rva(0x1010),
+                                         TestExport::Forwarder("OTHER.Target"),
+                                         TestExport::Rva(0x1020)],
+                                       &[("First", 0), ("Third", 2), ("Forwarded", 3)]);
+        exporting_image(&[0xC3], &exports)
+    }
+
+    #[test]
+    fn exports_by_index() {
+        let image = exporting();
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        let export = |name: Option<&str>, ordinal, target| {
+            Export {
+                name: name.map(str::to_owned),
+                ordinal: ordinal,
+                target: target,
+            }
+        };
+
+        assert_eq!(pe.exports(),
+                   vec![export(Some("First"), 5, ExportTarget::Rva(0x1000)),
+                        export(Some("Third"), 7, ExportTarget::Rva(0x1010)),
+                        export(Some("Forwarded"),
+                               8,
+                               ExportTarget::Forwarder("OTHER.Target".to_owned())),
+                        export(None, 9, ExportTarget::Rva(0x1020))]);
+
+        // No export directory.
+        let image = build_image(&[&[0xC3]]);
+        assert_eq!(Pe::parse(&image, Layout::Image).unwrap().exports(), vec![]);
+    }
+
+    #[test]
+    fn export_by_name_and_ordinal() {
+        let image = exporting();
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(pe.export(ExportRef::Name("Third")), Some(ExportTarget::Rva(0x1010)));
+        assert_eq!(pe.export(ExportRef::Name("third")), None);
+        assert_eq!(pe.export(ExportRef::Name("Missing")), None);
+        assert_eq!(pe.export(ExportRef::Name("Forwarded")),
+                   Some(ExportTarget::Forwarder("OTHER.Target".to_owned())));
+
+        // The ordinals start at the base.
+        assert_eq!(pe.export(ExportRef::Ordinal(5)), Some(ExportTarget::Rva(0x1000)));
+        assert_eq!(pe.export(ExportRef::Ordinal(7)), Some(ExportTarget::Rva(0x1010)));
+        assert_eq!(pe.export(ExportRef::Ordinal(9)), Some(ExportTarget::Rva(0x1020)));
+        assert_eq!(pe.export(ExportRef::Ordinal(1)), None);
+        assert_eq!(pe.export(ExportRef::Ordinal(4)), None);
+        // A gap in the table.
+        assert_eq!(pe.export(ExportRef::Ordinal(6)), None);
+        assert_eq!(pe.export(ExportRef::Ordinal(10)), None);
+    }
+
+    #[test]
+    fn parse_forwarders() {
+        assert_eq!(parse_forwarder("NTDLL.RtlAllocateHeap"),
+                   Some(("NTDLL", ExportRef::Name("RtlAllocateHeap"))));
+        assert_eq!(parse_forwarder("NTDLL.#12"), Some(("NTDLL", ExportRef::Ordinal(12))));
+        assert_eq!(parse_forwarder("api-ms-win-core-heap-l1-1-0.HeapAlloc"),
+                   Some(("api-ms-win-core-heap-l1-1-0", ExportRef::Name("HeapAlloc"))));
+
+        for &forwarder in &["NTDLL", ".RtlAllocateHeap", "NTDLL.", "NTDLL.#", "NTDLL.#x",
+                            "NTDLL.#70000"] {
+            assert_eq!(parse_forwarder(forwarder), None, "{}", forwarder);
+        }
+    }
+
+    #[test]
+    fn imports_by_name_and_ordinal() {
+        let imports = import_directory(0x2000,
+                                       &[("KERNEL32.dll",
+                                          &[TestImport::Name("LoadLibraryA"),
+                                            TestImport::Ordinal(5),
+                                            TestImport::Name("FreeLibrary")]),
+                                         ("USER32.dll", &[TestImport::Name("MessageBoxW")])]);
+        let image = importing_image(&[0xC3], &imports);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        let import = |module: &str, name: Option<&str>, ordinal: Option<u16>, iat_rva: usize| {
+            Import {
+                module: module.to_owned(),
+                name: name.map(str::to_owned),
+                ordinal: ordinal,
+                iat_rva: iat_rva,
+            }
+        };
+
+        // Each address table follows the lookup table of its module, KERNEL32's comes right after
+        // the three descriptors, USER32's after the names of KERNEL32's imports.
+        assert_eq!(pe.imports(),
+                   vec![import("KERNEL32.dll", Some("LoadLibraryA"), None, 0x2000 + 60 + 16),
+                        import("KERNEL32.dll", None, Some(5), 0x2000 + 60 + 20),
+                        import("KERNEL32.dll", Some("FreeLibrary"), None, 0x2000 + 60 + 24),
+                        import("USER32.dll", Some("MessageBoxW"), None, 0x2000 + 142)]);
+
+        // The names didn't come from the address tables, the loader has overwritten those.
+        assert_eq!(pe.read_u32(0x2000 + 60 + 16), Some(imported_address(0, 0)));
+        assert_eq!(pe.read_u32(0x2000 + 142), Some(imported_address(1, 0)));
+    }
+
+    #[test]
+    fn imports_without_directory_or_lookup_table() {
+        assert!(Pe::parse(&build_image(&[&[0xC3]]), Layout::Image).unwrap().imports().is_empty());
+
+        // Without a lookup table only a file has the names, in the address table.
+        let mut imports = import_directory(0x2000, &[("A.dll", &[TestImport::Name("F")])]);
+        put_u32(&mut imports, 0, 0);
+        let image = importing_image(&[0xC3], &imports);
+        assert!(Pe::parse(&image, Layout::Image).unwrap().imports().is_empty());
+    }
+
+    #[test]
+    fn parse_bad_signatures() {
+        let mut file = build(&[TEXT], 0x2000);
+        put_u16(&mut file, OPTIONAL_HEADER, 0x107);
+        assert_eq!(Pe::parse(&file, Layout::File).err(),
+                   Some(Error::BadOptionalHeaderMagic(0x107)));
+
+        put_u32(&mut file, NT, 0);
+        assert_eq!(Pe::parse(&file, Layout::File).err(), Some(Error::BadNtSignature));
+
+        put_u16(&mut file, 0, 0);
+        assert_eq!(Pe::parse(&file, Layout::File).err(), Some(Error::BadDosSignature));
+    }
+
+    #[test]
+    fn map_image_checks_size() {
+        assert_eq!(map_image(&build(&[TEXT, DATA], 0x7FFF_0000)).err(),
+                   Some(Error::BadImageSize(0x7FFF_0000)));
+        // Past the last section by more than the alignment.
+        assert_eq!(map_image(&build(&[TEXT, DATA], 0x4000)).err(),
+                   Some(Error::BadImageSize(0x4000)));
+        assert_eq!(map_image(&build(&[TEXT, DATA], 0x2400)).err(),
+                   Some(Error::SectionOutOfRange(1)));
+    }
+
+    #[test]
+    fn map_image_out_of_range_sections() {
+        let far = TestSection {
+            virtual_address: 0xFFFF_F000,
+            virtual_size: 0x2000,
+            ..DATA
+        };
+        assert_eq!(map_image(&build(&[TEXT, far], 0x3000)).err(),
+                   Some(Error::SectionOutOfRange(1)));
+
+        // Raw data past the end of the file is cut off, and the rest is zero-filled.
+        let mut file = build(&[TEXT, DATA], 0x3000);
+        file.truncate(0x402);
+
+        let pe = Pe::parse(&file, Layout::File).unwrap();
+        assert_eq!(pe.section_data(pe.section(".data").unwrap()), &[1, 2]);
+
+        let image = map_image(&file).unwrap();
+        assert_eq!(&image[0x2000..0x2004], &[1, 2, 0, 0]);
+    }
+}
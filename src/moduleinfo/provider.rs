@@ -0,0 +1,95 @@
+// Where modules come from. The hooking code only looks at modules through this trait, so the
+// selection logic can run against made up modules as well as against the current process.
+
+use moduleinfo::ModuleInfo;
+use std::path::PathBuf;
+use winapi::*;
+
+pub trait ModuleProvider: Send + Sync {
+    // A loaded module by its file name, like GetModuleHandle().
+    fn get(&self, name: &str) -> Option<ModuleInfo>;
+    fn get_loaded(&self) -> Vec<ModuleInfo>;
+    fn get_module_info(&self, handle: HMODULE) -> Option<ModuleInfo>;
+    fn get_module_path(&self, handle: HMODULE) -> Option<PathBuf>;
+}
+
+#[cfg(test)]
+struct FakeModule {
+    data: Vec<u8>,
+    path: PathBuf,
+}
+
+// Serves modules made from byte buffers, for running the module selection logic on any platform.
+// The handle and the base of a fake module are the address of its buffer.
+#[cfg(test)]
+#[derive(Default)]
+pub struct Fake {
+    modules: Vec<FakeModule>,
+}
+
+#[cfg(test)]
+impl Fake {
+    pub fn new() -> Fake {
+        Fake::default()
+    }
+
+    // Adds a module, the data is usually a PE image. Empty modules get a byte so that every
+    // module has a distinct handle.
+    pub fn add<P: Into<PathBuf>>(&mut self, path: P, mut data: Vec<u8>) -> ModuleInfo {
+        if data.is_empty() {
+            data.push(0);
+        }
+
+        self.modules.push(FakeModule {
+            data: data,
+            path: path.into(),
+        });
+
+        self.module_info(self.modules.last().unwrap())
+    }
+
+    // Unloads the module, like FreeLibrary().
+    pub fn remove(&mut self, handle: HMODULE) {
+        self.modules.retain(|module| module.data.as_ptr() as HMODULE != handle);
+    }
+
+    fn module_info(&self, module: &FakeModule) -> ModuleInfo {
+        ModuleInfo {
+            handle: module.data.as_ptr() as HMODULE,
+            base: module.data.as_ptr() as LPVOID,
+            size: module.data.len(),
+        }
+    }
+
+    fn find(&self, handle: HMODULE) -> Option<&FakeModule> {
+        self.modules.iter().find(|module| module.data.as_ptr() as HMODULE == handle)
+    }
+}
+
+#[cfg(test)]
+impl ModuleProvider for Fake {
+    fn get(&self, name: &str) -> Option<ModuleInfo> {
+        let name = name.to_lowercase();
+
+        self.modules
+            .iter()
+            .find(|module| {
+                module.path
+                      .file_name()
+                      .map_or(false, |filename| filename.to_string_lossy().to_lowercase() == name)
+            })
+            .map(|module| self.module_info(module))
+    }
+
+    fn get_loaded(&self) -> Vec<ModuleInfo> {
+        self.modules.iter().map(|module| self.module_info(module)).collect()
+    }
+
+    fn get_module_info(&self, handle: HMODULE) -> Option<ModuleInfo> {
+        self.find(handle).map(|module| self.module_info(module))
+    }
+
+    fn get_module_path(&self, handle: HMODULE) -> Option<PathBuf> {
+        self.find(handle).map(|module| module.path.clone())
+    }
+}
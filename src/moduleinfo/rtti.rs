@@ -0,0 +1,239 @@
+// Finding classes and their vtables through the RTTI that MSVC emits for every polymorphic class.
+//
+// Every vtable is preceded by a pointer to a complete object locator, which points to the type
+// descriptor with the mangled class name and to the class hierarchy descriptor with the base
+// classes. All of these pointers are absolute addresses, so the lookups need to know where the
+// module is loaded: the actual base for a live module, or the image base for a file on disk.
+
+use std::cmp;
+use super::pe::Pe;
+
+// The mangled names of classes and structs.
+const CLASS_PREFIX: &'static str = ".?AV";
+const STRUCT_PREFIX: &'static str = ".?AU";
+const NAME_SUFFIX: &'static str = "@@";
+
+// Nothing sane has more bases than this.
+const MAX_BASE_CLASSES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDescriptor {
+    pub rva: usize,
+    pub name: String,
+}
+
+impl TypeDescriptor {
+    // The name as it's written in the source, or the mangled name if it can't be demangled.
+    pub fn demangled_name(&self) -> String {
+        demangle(&self.name).unwrap_or_else(|| self.name.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vtable {
+    pub rva: usize,
+    // Where the subobject that uses this vtable is in the complete object. The primary vtable is
+    // at offset 0, the others come from multiple inheritance.
+    pub offset: u32,
+    // The complete object locator.
+    pub locator: usize,
+}
+
+// Turns `CHL1GameMovement` or `ns::Class` into `.?AVCHL1GameMovement@@` or `.?AVClass@ns@@`.
+// Names that are already mangled are returned as is.
+pub fn mangle(name: &str, prefix: &str) -> String {
+    if name.starts_with(".?A") {
+        return name.to_owned();
+    }
+
+    let mut rv = prefix.to_owned();
+
+    for part in name.rsplit("::") {
+        rv.push_str(part);
+        rv.push('@');
+    }
+
+    rv.push('@');
+    rv
+}
+
+// The reverse of mangle(). Templates and anything else fancy aren't supported.
+pub fn demangle(mangled: &str) -> Option<String> {
+    if !(mangled.starts_with(CLASS_PREFIX) || mangled.starts_with(STRUCT_PREFIX)) ||
+       !mangled.ends_with(NAME_SUFFIX) {
+        return None;
+    }
+
+    let body = &mangled[CLASS_PREFIX.len()..mangled.len() - NAME_SUFFIX.len()];
+
+    if body.is_empty() || body.contains('?') || body.contains('$') {
+        return None;
+    }
+
+    let parts = body.split('@').collect::<Vec<&str>>();
+
+    if parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+
+    Some(parts.into_iter().rev().collect::<Vec<&str>>().join("::"))
+}
+
+// Every class and struct with a type descriptor.
+pub fn type_descriptors(pe: &Pe) -> Vec<TypeDescriptor> {
+    let mut rv = Vec::new();
+
+    for section in pe.sections.iter().filter(|section| !section.is_executable()) {
+        let data = pe.section_data(section);
+        let start = section.virtual_address as usize;
+
+        // The name comes after the vtable pointer and the spare field.
+        let mut offset = 8;
+        while offset + 4 <= data.len() {
+            let prefix = &data[offset..offset + 4];
+
+            if prefix == CLASS_PREFIX.as_bytes() || prefix == STRUCT_PREFIX.as_bytes() {
+                if let Some(name) = pe.read_cstr(start + offset) {
+                    rv.push(TypeDescriptor {
+                        rva: start + offset - 8,
+                        name: String::from_utf8_lossy(name).into_owned(),
+                    });
+                }
+            }
+
+            // Type descriptors are 4-byte aligned.
+            offset += 4;
+        }
+    }
+
+    rv
+}
+
+// Finds the type descriptors of the class, by its mangled or its plain name.
+pub fn find_type_descriptors(pe: &Pe, name: &str) -> Vec<TypeDescriptor> {
+    let class = mangle(name, CLASS_PREFIX);
+    let structure = mangle(name, STRUCT_PREFIX);
+
+    type_descriptors(pe)
+        .into_iter()
+        .filter(|descriptor| descriptor.name == class || descriptor.name == structure)
+        .collect()
+}
+
+// The complete object locators that point to the type descriptor, one per vtable.
+pub fn find_locators(pe: &Pe, base: usize, descriptor: &TypeDescriptor) -> Vec<usize> {
+    let va = (base + descriptor.rva) as u32;
+
+    find_u32(pe, va)
+        .into_iter()
+        .filter(|&rva| rva >= 12)
+        // The type descriptor is the fourth field, and the signature is zero for 32-bit code.
+        .map(|rva| rva - 12)
+        .filter(|&locator| pe.read_u32(locator) == Some(0))
+        .filter(|&locator| {
+            pe.read_u32(locator + 16)
+              .and_then(|hierarchy| to_rva(pe, base, hierarchy))
+              .is_some()
+        })
+        .collect()
+}
+
+pub fn find_vtables(pe: &Pe, base: usize, name: &str) -> Vec<Vtable> {
+    let mut rv = Vec::new();
+
+    for descriptor in find_type_descriptors(pe, name) {
+        for locator in find_locators(pe, base, &descriptor) {
+            let offset = pe.read_u32(locator + 4).unwrap_or(0);
+
+            for reference in find_u32(pe, (base + locator) as u32) {
+                rv.push(Vtable {
+                    rva: reference + 4,
+                    offset: offset,
+                    locator: locator,
+                });
+            }
+        }
+    }
+
+    rv.sort_by_key(|vtable| (vtable.offset, vtable.rva));
+    rv
+}
+
+// The vtable of the class itself rather than of one of its secondary bases. Fails if there's more
+// than one such vtable, which happens when several classes share the name.
+pub fn find_primary_vtable(pe: &Pe, base: usize, name: &str) -> Option<usize> {
+    let primary = find_vtables(pe, base, name)
+        .into_iter()
+        .filter(|vtable| vtable.offset == 0)
+        .collect::<Vec<Vtable>>();
+
+    if primary.len() == 1 {
+        Some(primary[0].rva)
+    } else {
+        None
+    }
+}
+
+// The class and all of its bases, starting with the class itself, from the class hierarchy
+// descriptor the locator points to.
+pub fn base_classes(pe: &Pe, base: usize, locator: usize) -> Vec<TypeDescriptor> {
+    let mut rv = Vec::new();
+
+    let hierarchy = match pe.read_u32(locator + 16).and_then(|va| to_rva(pe, base, va)) {
+        Some(hierarchy) => hierarchy,
+        None => return rv,
+    };
+
+    let count = pe.read_u32(hierarchy + 8).unwrap_or(0) as usize;
+    let array = match pe.read_u32(hierarchy + 12).and_then(|va| to_rva(pe, base, va)) {
+        Some(array) => array,
+        None => return rv,
+    };
+
+    for i in 0..cmp::min(count, MAX_BASE_CLASSES) {
+        let descriptor = pe.read_u32(array + i * 4)
+                           .and_then(|va| to_rva(pe, base, va))
+                           .and_then(|class| pe.read_u32(class))
+                           .and_then(|va| to_rva(pe, base, va));
+
+        if let Some(descriptor) = descriptor {
+            if let Some(name) = pe.read_cstr(descriptor + 8) {
+                rv.push(TypeDescriptor {
+                    rva: descriptor,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                });
+            }
+        }
+    }
+
+    rv
+}
+
+fn to_rva(pe: &Pe, base: usize, va: u32) -> Option<usize> {
+    let rva = (va as usize).wrapping_sub(base);
+
+    if rva < pe.size_of_image as usize {
+        Some(rva)
+    } else {
+        None
+    }
+}
+
+// RVAs of the 4-byte aligned occurrences of the value in the non-executable sections.
+fn find_u32(pe: &Pe, value: u32) -> Vec<usize> {
+    let bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8];
+
+    let mut rv = Vec::new();
+
+    for section in pe.sections.iter().filter(|section| !section.is_executable()) {
+        let start = section.virtual_address as usize;
+
+        rv.extend(pe.section_data(section)
+                    .chunks(4)
+                    .enumerate()
+                    .filter(|&(_, chunk)| chunk == &bytes[..])
+                    .map(|(i, _)| start + i * 4));
+    }
+
+    rv
+}
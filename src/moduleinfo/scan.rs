@@ -0,0 +1,144 @@
+// Pattern scans over a module mapped the way the loader does it. The offsets are RVAs. Only the
+// headers of the image are looked at, so this works the same on a module loaded into the process
+// and on a DLL mapped from disk by the offline tools.
+
+use pattern::{FindError, Pattern, PatternSet};
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+use super::pe::{Layout, Pe, Section};
+
+// Which part of the module a pattern scan covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope<'a> {
+    Image,
+    Executable,
+    Section(&'a str),
+}
+
+// Ranges of RVAs that are covered by the scope.
+pub fn ranges(image: &[u8], scope: Scope) -> Vec<Range<usize>> {
+    let whole = Range {
+        start: 0,
+        end: image.len(),
+    };
+
+    let pe = match Pe::parse(image, Layout::Image) {
+        Ok(pe) => pe,
+        // Without the headers we can't do better than the whole module.
+        Err(_) => return vec![whole],
+    };
+
+    let ranges = match scope {
+        Scope::Image => return vec![whole],
+        Scope::Executable => {
+            pe.sections
+              .iter()
+              .filter(|section| section.is_executable())
+              .map(Section::rva_range)
+              .collect::<Vec<_>>()
+        }
+        Scope::Section(name) => {
+            pe.sections
+              .iter()
+              .filter(|section| section.name == name)
+              .map(Section::rva_range)
+              .collect::<Vec<_>>()
+        }
+    };
+
+    ranges.into_iter()
+          .filter(|range| range.start < image.len())
+          .map(|range| range.start..cmp::min(range.end, image.len()))
+          .collect()
+}
+
+pub fn find_in(image: &[u8], scope: Scope, pattern: Pattern) -> Option<usize> {
+    ranges(image, scope)
+        .into_iter()
+        .filter_map(|range| {
+            let start = range.start;
+            pattern.find_in(&image[range]).map(|offset| start + offset)
+        })
+        .next()
+}
+
+pub fn find_all_in(image: &[u8], scope: Scope, pattern: Pattern) -> Vec<usize> {
+    let mut rv = Vec::new();
+
+    for range in ranges(image, scope) {
+        let start = range.start;
+
+        rv.extend(pattern.find_all_in(&image[range]).into_iter().map(|offset| start + offset));
+    }
+
+    rv
+}
+
+// Like find_in(), but fails if the pattern doesn't match exactly one location.
+pub fn find_unique_in(image: &[u8], scope: Scope, pattern: Pattern) -> Result<usize, FindError> {
+    FindError::unique(&find_all_in(image, scope, pattern))
+}
+
+// Finds every pattern with a single pass over the module instead of one pass per pattern. The
+// matches are keyed by the pattern names, patterns with the same name share their matches.
+pub fn find_many_in<'a, K>(image: &[u8],
+                           scope: Scope,
+                           patterns: &[(K, Pattern<'a>)])
+                           -> HashMap<K, Vec<usize>>
+    where K: Copy + Eq + Hash
+{
+    let set = PatternSet::new(&patterns.iter().map(|&(_, pattern)| pattern).collect::<Vec<_>>());
+
+    let mut rv = patterns.iter()
+                         .map(|&(name, _)| (name, Vec::new()))
+                         .collect::<HashMap<K, Vec<usize>>>();
+
+    for range in ranges(image, scope) {
+        let start = range.start;
+
+        for (&(name, _), matches) in patterns.iter().zip(set.find_all_in(&image[range])) {
+            rv.get_mut(&name)
+              .unwrap()
+              .extend(matches.into_iter().map(|offset| start + offset));
+        }
+    }
+
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pattern::OwnedPattern;
+    use super::super::pe::builder::*;
+
+    #[test]
+    fn find_many_in_scopes_and_names() {
+        let code = [0x55, 0x8B, 0xEC, 0x8B, 0x45, 0x08, 0x5D, 0xC3];
+        let data = [0x8B, 0x45, 0x08, 0x00];
+        let image = build_image(&[&code, &data]);
+
+        let load = OwnedPattern::parse("8B 45 08").unwrap();
+        let prologue = OwnedPattern::parse("55 8B EC").unwrap();
+        let missing = OwnedPattern::parse("CC CC").unwrap();
+        let patterns = [("load", load.as_pattern()),
+                        ("function", prologue.as_pattern()),
+                        ("function", load.as_pattern()),
+                        ("missing", missing.as_pattern())];
+
+        let matches = find_many_in(&image, Scope::Executable, &patterns);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches["load"], vec![0x1003]);
+        assert_eq!(matches["function"], vec![0x1000, 0x1003]);
+        assert_eq!(matches["missing"], vec![]);
+
+        let matches = find_many_in(&image, Scope::Section(".rdata"), &patterns);
+        assert_eq!(matches["load"], vec![0x2000]);
+        assert_eq!(matches["function"], vec![0x2000]);
+
+        assert_eq!(find_unique_in(&image, Scope::Image, load.as_pattern()),
+                   Err(FindError::Ambiguous(2)));
+    }
+}
@@ -0,0 +1,200 @@
+// The version resource of a module, VS_VERSIONINFO. It tells modules with the same file name
+// apart, e.g. the server.dll of one game from the server.dll of another.
+
+use super::pe::{self, Pe};
+
+const RT_VERSION: u32 = 16;
+const VS_FFI_SIGNATURE: u32 = 0xFEEF04BD;
+
+// A corrupted directory could claim up to 131070 entries.
+const MAX_RESOURCE_ENTRIES: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionInfo {
+    // The file version from VS_FIXEDFILEINFO, the most significant part first.
+    pub file_version: Option<[u16; 4]>,
+    // The StringFileInfo entries of every language, like ("ProductName", "Half-Life 2").
+    pub strings: Vec<(String, String)>,
+}
+
+impl VersionInfo {
+    // The value of the first string with the key, ignoring the case of the key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|string| string.0.to_lowercase() == key.to_lowercase())
+            .map(|string| &string.1[..])
+    }
+}
+
+// One node of VS_VERSIONINFO: a key, a value and the child nodes.
+struct Block<'a> {
+    key: String,
+    value: &'a [u8],
+    is_text: bool,
+    children: &'a [u8],
+}
+
+pub fn version_info(pe: &Pe) -> Option<VersionInfo> {
+    let data = match find_version_resource(pe) {
+        Some(data) => data,
+        None => return None,
+    };
+
+    let root = match parse_block(data) {
+        Some((root, _)) => root,
+        None => return None,
+    };
+
+    let mut rv = VersionInfo::default();
+
+    if pe::read_u32(root.value, 0).ok() == Some(VS_FFI_SIGNATURE) {
+        if let (Ok(ms), Ok(ls)) = (pe::read_u32(root.value, 8), pe::read_u32(root.value, 12)) {
+            rv.file_version = Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16]);
+        }
+    }
+
+    for info in blocks(root.children).into_iter().filter(|block| block.key == "StringFileInfo") {
+        for table in blocks(info.children) {
+            for string in blocks(table.children) {
+                let value = if string.is_text {
+                    utf16_until_nul(string.value)
+                } else {
+                    String::from_utf8_lossy(string.value).trim_right_matches('\0').to_owned()
+                };
+
+                rv.strings.push((string.key, value));
+            }
+        }
+    }
+
+    Some(rv)
+}
+
+// The data of the first RT_VERSION resource, there's only one in practice.
+fn find_version_resource<'a>(pe: &Pe<'a>) -> Option<&'a [u8]> {
+    let root = match pe.data_directory(pe::IMAGE_DIRECTORY_ENTRY_RESOURCE) {
+        Some(directory) => directory.virtual_address as usize,
+        None => return None,
+    };
+
+    let names = match resource_entries(pe, root, root)
+        .into_iter()
+        .find(|&(id, _, is_directory)| id == Some(RT_VERSION) && is_directory) {
+        Some((_, names, _)) => names,
+        None => return None,
+    };
+
+    let languages = match resource_entries(pe, root, names)
+        .into_iter()
+        .find(|&(_, _, is_directory)| is_directory) {
+        Some((_, languages, _)) => languages,
+        None => return None,
+    };
+
+    let entry = match resource_entries(pe, root, languages)
+        .into_iter()
+        .find(|&(_, _, is_directory)| !is_directory) {
+        Some((_, entry, _)) => entry,
+        None => return None,
+    };
+
+    match (pe.read_u32(entry), pe.read_u32(entry + 4)) {
+        (Some(rva), Some(size)) => pe.read(rva as usize, size as usize),
+        _ => None,
+    }
+}
+
+// The entries of IMAGE_RESOURCE_DIRECTORY: the ID unless it's named, the RVA of what the entry
+// points to, and whether that's another directory.
+fn resource_entries(pe: &Pe, root: usize, directory: usize) -> Vec<(Option<u32>, usize, bool)> {
+    let count = match (pe.read_u16(directory + 12), pe.read_u16(directory + 14)) {
+        (Some(named), Some(ids)) => named as usize + ids as usize,
+        _ => return Vec::new(),
+    };
+
+    (0..count)
+        .take(MAX_RESOURCE_ENTRIES)
+        .filter_map(|i| {
+            let entry = directory + 16 + i * 8;
+
+            match (pe.read_u32(entry), pe.read_u32(entry + 4)) {
+                (Some(name), Some(offset)) => {
+                    let id = if name & 0x80000000 == 0 { Some(name) } else { None };
+
+                    Some((id, root + (offset & 0x7FFFFFFF) as usize, offset & 0x80000000 != 0))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+// Parses the block at the start of the data. Also returns where the next sibling starts.
+fn parse_block<'a>(data: &'a [u8]) -> Option<(Block<'a>, usize)> {
+    let (len, value_len, kind) = match (pe::read_u16(data, 0),
+                                        pe::read_u16(data, 2),
+                                        pe::read_u16(data, 4)) {
+        (Ok(len), Ok(value_len), Ok(kind)) => (len as usize, value_len as usize, kind),
+        _ => return None,
+    };
+
+    if len < 6 || len > data.len() {
+        return None;
+    }
+
+    let block = &data[..len];
+
+    let key_len = block[6..].chunks(2)
+                            .take_while(|c| c.len() == 2 && *c != &[0, 0][..])
+                            .count();
+    let key = utf16_until_nul(&block[6..]);
+
+    let value_start = align4(6 + (key_len + 1) * 2);
+    // Text values are measured in characters.
+    let is_text = kind == 1;
+    let value_end = value_start + if is_text { value_len * 2 } else { value_len };
+
+    let value_start = if value_start < len { value_start } else { len };
+    let value_end = if value_end < len { value_end } else { len };
+    let children_start = if align4(value_end) < len { align4(value_end) } else { len };
+
+    let rv = Block {
+        key: key,
+        value: &block[value_start..value_end],
+        is_text: is_text,
+        children: &block[children_start..],
+    };
+
+    Some((rv, align4(len)))
+}
+
+fn blocks<'a>(mut data: &'a [u8]) -> Vec<Block<'a>> {
+    let mut rv = Vec::new();
+
+    while let Some((block, next)) = parse_block(data) {
+        rv.push(block);
+
+        if next >= data.len() {
+            break;
+        }
+
+        data = &data[next..];
+    }
+
+    rv
+}
+
+fn utf16_until_nul(data: &[u8]) -> String {
+    let units = data.chunks(2)
+                    .filter(|c| c.len() == 2)
+                    .map(|c| c[0] as u16 | (c[1] as u16) << 8)
+                    .take_while(|&c| c != 0)
+                    .collect::<Vec<u16>>();
+
+    String::from_utf16_lossy(&units)
+}
@@ -0,0 +1,98 @@
+// The modules of the current process.
+
+use kernel32;
+use psapi;
+use std::{mem, ptr};
+use std::path::PathBuf;
+use super::ModuleInfo;
+use super::provider::ModuleProvider;
+use utils;
+use widestring::WideCStr;
+use winapi::*;
+
+pub struct Windows;
+
+pub static WINDOWS: Windows = Windows;
+
+impl ModuleProvider for Windows {
+    fn get(&self, name: &str) -> Option<ModuleInfo> {
+        unsafe {
+            let handle = kernel32::GetModuleHandleW(utils::utf16(name).as_ptr());
+
+            if handle.is_null() {
+                return None;
+            }
+
+            self.get_module_info(handle)
+        }
+    }
+
+    fn get_loaded(&self) -> Vec<ModuleInfo> {
+        let mut modules: Vec<HMODULE> = Vec::new();
+
+        // Modules can be loaded between the calls, so repeat until the buffer is big enough.
+        loop {
+            let mut size_needed: DWORD = 0;
+
+            let success = unsafe {
+                psapi::EnumProcessModules(kernel32::GetCurrentProcess(),
+                                          modules.as_mut_ptr(),
+                                          (modules.len() * mem::size_of::<HMODULE>()) as DWORD,
+                                          &mut size_needed)
+            };
+
+            if success == 0 {
+                return Vec::new();
+            }
+
+            let module_count = size_needed as usize / mem::size_of::<HMODULE>();
+
+            if module_count <= modules.len() {
+                modules.truncate(module_count);
+                break;
+            }
+
+            modules.resize(module_count, ptr::null_mut());
+        }
+
+        modules.into_iter()
+               .filter_map(|handle| self.get_module_info(handle))
+               .collect()
+    }
+
+    fn get_module_info(&self, handle: HMODULE) -> Option<ModuleInfo> {
+        unsafe {
+            let mut info = mem::uninitialized::<MODULEINFO>();
+
+            if psapi::GetModuleInformation(kernel32::GetCurrentProcess(),
+                                           handle,
+                                           &mut info,
+                                           mem::size_of::<MODULEINFO>() as DWORD) != 0 {
+                Some(ModuleInfo {
+                    handle: handle,
+                    base: info.lpBaseOfDll,
+                    size: info.SizeOfImage as usize,
+                })
+            } else {
+                None
+            }
+        }
+    }
+
+    fn get_module_path(&self, handle: HMODULE) -> Option<PathBuf> {
+        unsafe {
+            let mut filename: [WCHAR; MAX_PATH] = mem::uninitialized();
+
+            let len = kernel32::GetModuleFileNameW(handle,
+                                                   filename.as_mut_ptr(),
+                                                   MAX_PATH as DWORD);
+            if len > 0 {
+                Some(PathBuf::from(
+                    WideCStr::from_ptr_with_nul(filename.as_ptr(), len as usize).to_os_string()
+                ))
+            } else {
+                None
+            }
+        }
+    }
+}
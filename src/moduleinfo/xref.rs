@@ -0,0 +1,204 @@
+// Locating functions through the strings they reference, for when the byte patterns break.
+
+use pattern::{FindError, OwnedPattern};
+use super::pe::Pe;
+
+// How far back from a string reference we look for the start of the function.
+const MAX_FUNCTION_SIZE: usize = 0x4000;
+
+// RVAs of every occurrence of the NUL-terminated string in the non-executable sections.
+pub fn find_string(pe: &Pe, string: &str) -> Vec<usize> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+
+    find_bytes(pe, &bytes, false)
+}
+
+// RVAs of `push imm32` and `mov reg, imm32` instructions in the executable sections whose
+// immediate is the given virtual address.
+pub fn find_references(pe: &Pe, va: u32) -> Vec<usize> {
+    let imm = [va as u8, (va >> 8) as u8, (va >> 16) as u8, (va >> 24) as u8];
+
+    find_bytes(pe, &imm, true)
+        .into_iter()
+        .filter(|&rva| rva > 0)
+        .map(|rva| rva - 1)
+        .filter(|&rva| match pe.read(rva, 1).map(|b| b[0]) {
+            // push imm32
+            Some(0x68) => true,
+            // mov reg, imm32
+            Some(opcode) => opcode >= 0xB8 && opcode <= 0xBF,
+            None => false,
+        })
+        .collect()
+}
+
+// Walks back from an instruction to the start of the function containing it. Functions are
+// recognized by the padding the compiler puts between them: at least two int3 bytes, or an int3
+// or ret right before a 16-byte aligned address. Nops aren't considered since the compiler also
+// uses them to align loops inside functions.
+pub fn function_start(pe: &Pe, rva: usize) -> Option<usize> {
+    let section = match pe.section_for_rva(rva) {
+        Some(section) if section.is_executable() => section,
+        _ => return None,
+    };
+
+    let section_start = section.virtual_address as usize;
+    let limit = if rva - section_start > MAX_FUNCTION_SIZE {
+        rva - MAX_FUNCTION_SIZE
+    } else {
+        section_start
+    };
+
+    let mut start = rva;
+    while start > limit {
+        if let Some(prev) = pe.read(start - 2, 2) {
+            if prev[0] == 0xCC && prev[1] == 0xCC {
+                return Some(start);
+            }
+
+            if start % 16 == 0 && (prev[1] == 0xCC || prev[1] == 0xC3) {
+                return Some(start);
+            }
+        }
+
+        start -= 1;
+    }
+
+    None
+}
+
+// Finds the function that references the string. Fails if there's no such function or if
+// several different functions reference the string. The base is where the code expects the image
+// to be: the load address for a module that the loader relocated, or the image base for a file.
+pub fn find_function_by_string(pe: &Pe, base: usize, string: &str) -> Result<usize, FindError> {
+    let mut functions = Vec::new();
+
+    for string_rva in find_string(pe, string) {
+        let va = (base + string_rva) as u32;
+
+        for reference in find_references(pe, va) {
+            if let Some(function) = function_start(pe, reference) {
+                if !functions.contains(&function) {
+                    functions.push(function);
+                }
+            }
+        }
+    }
+
+    FindError::unique(&functions)
+}
+
+fn find_bytes(pe: &Pe, bytes: &[u8], executable: bool) -> Vec<usize> {
+    let pattern = OwnedPattern {
+        bytes: bytes.iter().map(|&b| (b, 0xFF)).collect(),
+        captures: Vec::new(),
+    };
+
+    let mut rv = Vec::new();
+
+    for section in pe.sections.iter().filter(|section| section.is_executable() == executable) {
+        let start = section.virtual_address as usize;
+
+        rv.extend(pattern.as_pattern()
+                         .find_all_in(pe.section_data(section))
+                         .into_iter()
+                         .map(|offset| start + offset));
+    }
+
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pattern::FindError;
+    use super::super::pe::{Layout, Pe};
+    use super::super::pe::builder::*;
+
+    const STRING: &'static [u8] = b"Bad entity\0";
+
+    // A function that pushes the address of the string, at `at` in a code section at 0x1000 that
+    // starts with int3 padding.
+    fn push_string(code: &mut Vec<u8>, at: usize, base: u32) {
+        let va = base + 0x2000 + 0x10;
+
+        code.resize(at, 0xCC);
+        code.extend_from_slice(&[0x55, 0x8B, 0xEC, 0x68]);
+        code.extend_from_slice(&[va as u8, (va >> 8) as u8, (va >> 16) as u8, (va >> 24) as u8]);
+        code.extend_from_slice(&[0x83, 0xC4, 0x04, 0x5D, 0xC3]);
+    }
+
+    fn strings() -> Vec<u8> {
+        let mut data = vec![0; 0x10];
+        data.extend_from_slice(STRING);
+        data
+    }
+
+    #[test]
+    fn find_string_and_references() {
+        let mut code = Vec::new();
+        push_string(&mut code, 0x20, IMAGE_BASE);
+        let image = build_image(&[&code, &strings()]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(find_string(&pe, "Bad entity"), vec![0x2010]);
+        // The terminator has to match too.
+        assert_eq!(find_string(&pe, "Bad"), vec![]);
+
+        assert_eq!(find_references(&pe, IMAGE_BASE + 0x2010), vec![0x1023]);
+        assert_eq!(find_references(&pe, IMAGE_BASE + 0x2000), vec![]);
+
+        assert_eq!(function_start(&pe, 0x1023), Some(0x1020));
+        // Outside of the code.
+        assert_eq!(function_start(&pe, 0x2010), None);
+    }
+
+    #[test]
+    fn function_start_padding() {
+        // A ret right before a 16-byte aligned address ends the previous function, but a single
+        // int3 that isn't followed by one doesn't.
+        let code = [0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90,
+                    0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0xC3,
+                    0x55, 0xCC, 0x8B, 0xEC, 0x90, 0x90];
+        let image = build_image(&[&code]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(function_start(&pe, 0x1014), Some(0x1010));
+        assert_eq!(function_start(&pe, 0x100F), None);
+    }
+
+    #[test]
+    fn find_function_by_string_base() {
+        let mut code = Vec::new();
+        push_string(&mut code, 0x20, IMAGE_BASE);
+        let image = build_image(&[&code, &strings()]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(find_function_by_string(&pe, IMAGE_BASE as usize, "Bad entity"), Ok(0x1020));
+        assert_eq!(find_function_by_string(&pe, IMAGE_BASE as usize, "Missing"),
+                   Err(FindError::NotFound));
+
+        // Relocated by the loader, so the code refers to the load address.
+        let mut code = Vec::new();
+        push_string(&mut code, 0x20, 0x0400_0000);
+        let image = build_image(&[&code, &strings()]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(find_function_by_string(&pe, 0x0400_0000, "Bad entity"), Ok(0x1020));
+        assert_eq!(find_function_by_string(&pe, IMAGE_BASE as usize, "Bad entity"),
+                   Err(FindError::NotFound));
+    }
+
+    #[test]
+    fn find_function_by_string_ambiguous() {
+        let mut code = Vec::new();
+        push_string(&mut code, 0x20, IMAGE_BASE);
+        push_string(&mut code, 0x40, IMAGE_BASE);
+        let image = build_image(&[&code, &strings()]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(find_function_by_string(&pe, IMAGE_BASE as usize, "Bad entity"),
+                   Err(FindError::Ambiguous(2)));
+    }
+}
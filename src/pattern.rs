@@ -1,22 +1,729 @@
+use libc;
+use std::{error, fmt};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+// Every byte is a (value, mask) pair: a data byte matches if `data & mask == value`. The mask is
+// 0xFF for exact bytes and 0x00 for wildcards, anything in between matches only some of the bits.
 #[derive(Clone, Copy)]
-pub struct Pattern(pub &'static [(u8, bool)]);
+pub struct Pattern<'a> {
+    pub bytes: &'a [(u8, u8)],
+    pub captures: &'a [Capture],
+}
+
+// A named position inside a pattern, usually the start of an address operand.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capture {
+    pub name: Cow<'static, str>,
+    pub offset: usize,
+}
 
-impl Pattern {
+impl<'a> Pattern<'a> {
     pub fn len(self) -> usize {
-        self.0.len()
+        self.bytes.len()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn capture_offset(self, name: &str) -> Option<usize> {
+        self.captures.iter().find(|capture| capture.name == name).map(|capture| capture.offset)
+    }
+
+    // Whether the data starts with the pattern.
+    pub fn compare(self, data: &[u8]) -> bool {
+        data.len() >= self.bytes.len() &&
+        self.bytes.iter().zip(data).all(|(&(b, m), &d)| d & m == b)
+    }
+
+    // Picks the fully matched byte that is least likely to occur in x86 code. The scanner searches
+    // for it with memchr() and only checks the full pattern at the candidate positions.
+    pub fn anchor(self) -> Option<(usize, u8)> {
+        self.bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(_, m))| m == 0xFF)
+            .min_by_key(|&(_, &(b, _))| byte_frequency(b))
+            .map(|(i, &(b, _))| (i, b))
+    }
+
+    pub fn find_in(self, data: &[u8]) -> Option<usize> {
+        self.find_from(data, 0)
+    }
+
+    pub fn find_all_in(self, data: &[u8]) -> Vec<usize> {
+        let mut rv = Vec::new();
+        let mut start = 0;
+
+        while let Some(offset) = self.find_from(data, start) {
+            rv.push(offset);
+            start = offset + 1;
+        }
+
+        rv
+    }
+
+    fn find_from(self, data: &[u8], mut start: usize) -> Option<usize> {
+        let len = self.len();
+
+        if self.is_empty() || data.len() < len {
+            return None;
+        }
+
+        // The last valid start position is data.len() - len, inclusive.
+        let last_start = data.len() - len;
+
+        let (anchor_offset, anchor) = match self.anchor() {
+            Some(anchor) => anchor,
+            // Nothing to anchor on, only partially masked bytes and wildcards.
+            None => {
+                return (start..last_start + 1).find(|&i| self.compare(&data[i..]));
+            }
+        };
+
+        while start <= last_start {
+            let haystack = &data[start + anchor_offset..last_start + anchor_offset + 1];
+
+            match memchr(anchor, haystack) {
+                Some(pos) => {
+                    let candidate = start + pos;
+
+                    if self.compare(&data[candidate..]) {
+                        return Some(candidate);
+                    }
+
+                    start = candidate + 1;
+                }
+                None => break,
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindError {
+    NotFound,
+    Ambiguous(usize),
+}
+
+impl fmt::Display for FindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FindError::NotFound => write!(f, "not found"),
+            FindError::Ambiguous(count) => write!(f, "ambiguous ({} matches)", count),
+        }
+    }
+}
+
+impl error::Error for FindError {
+    fn description(&self) -> &str {
+        match *self {
+            FindError::NotFound => "not found",
+            FindError::Ambiguous(_) => "ambiguous",
+        }
+    }
+}
+
+impl FindError {
+    // The only element, or why there isn't exactly one.
+    pub fn unique<T: Copy>(matches: &[T]) -> Result<T, FindError> {
+        match matches.len() {
+            0 => Err(FindError::NotFound),
+            1 => Ok(matches[0]),
+            count => Err(FindError::Ambiguous(count)),
+        }
+    }
+}
+
+// Finds several patterns with a single pass over the data. The patterns are indexed by their
+// anchor bytes, so every position costs a table lookup plus a full comparison only for the
+// patterns anchored on the byte found there.
+pub struct PatternSet<'a> {
+    patterns: Vec<Pattern<'a>>,
+    // For every byte value, the indices of the patterns anchored on it with the anchor offsets.
+    anchored: Vec<Vec<(usize, usize)>>,
+    // Patterns without a fully matched byte are compared at every position.
+    unanchored: Vec<usize>,
+}
+
+impl<'a> PatternSet<'a> {
+    pub fn new(patterns: &[Pattern<'a>]) -> PatternSet<'a> {
+        let mut anchored = vec![Vec::new(); 256];
+        let mut unanchored = Vec::new();
+
+        for (index, pattern) in patterns.iter().enumerate() {
+            if pattern.is_empty() {
+                continue;
+            }
+
+            match pattern.anchor() {
+                Some((offset, b)) => anchored[b as usize].push((index, offset)),
+                None => unanchored.push(index),
+            }
+        }
+
+        PatternSet {
+            patterns: patterns.to_vec(),
+            anchored: anchored,
+            unanchored: unanchored,
+        }
     }
 
-    pub fn compare(self, data: *const u8) -> bool {
-        for i in 0..self.0.len() {
-            let (b, m) = self.0[i];
+    // The offsets of all matches of every pattern, in the order the patterns were given.
+    pub fn find_all_in(&self, data: &[u8]) -> Vec<Vec<usize>> {
+        let mut rv = vec![Vec::new(); self.patterns.len()];
+
+        for (pos, &b) in data.iter().enumerate() {
+            for &(index, anchor_offset) in &self.anchored[b as usize] {
+                if pos < anchor_offset {
+                    continue;
+                }
+
+                let start = pos - anchor_offset;
 
-            unsafe {
-                if m && *data.offset(i as isize) != b {
-                    return false;
+                if self.patterns[index].compare(&data[start..]) {
+                    rv[index].push(start);
                 }
             }
+
+            for &index in &self.unanchored {
+                if self.patterns[index].compare(&data[pos..]) {
+                    rv[index].push(pos);
+                }
+            }
+        }
+
+        rv
+    }
+}
+
+fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let p = unsafe {
+        libc::memchr(haystack.as_ptr() as *const libc::c_void,
+                     needle as libc::c_int,
+                     haystack.len())
+    };
+
+    if p.is_null() {
+        None
+    } else {
+        Some(p as usize - haystack.as_ptr() as usize)
+    }
+}
+
+// Rough ranking of how common a byte is in compiled x86 code, higher is more common. Bytes that are
+// not listed are considered rare.
+fn byte_frequency(b: u8) -> u8 {
+    match b {
+        0x00 => 255,
+        0xFF => 250,
+        0xCC => 245,
+        0x8B => 240,
+        0x89 => 230,
+        0x24 => 225,
+        0x04 | 0x08 | 0x0C | 0x10 => 220,
+        0x44 | 0x45 | 0x4C | 0x4D => 210,
+        0x83 | 0x85 | 0x8D => 200,
+        0x01 | 0x02 | 0x03 => 190,
+        0xC0 | 0xC4 | 0xC7 | 0xEC => 180,
+        0x50 | 0x51 | 0x52 | 0x53 | 0x55 | 0x56 | 0x57 => 170,
+        0x58 | 0x59 | 0x5A | 0x5B | 0x5D | 0x5E | 0x5F => 160,
+        0x74 | 0x75 | 0xEB | 0xE8 | 0xE9 => 150,
+        0x0F | 0x33 | 0x3B | 0x6A | 0x68 | 0xC3 | 0x90 => 140,
+        0x14 | 0x18 | 0x1C | 0x20 | 0x28 | 0x30 | 0x40 => 130,
+        0x46 | 0x4E | 0x7E | 0x76 | 0xF1 | 0xF6 | 0xD9 => 120,
+        _ => 0,
+    }
+}
+
+impl<'a> fmt::Display for Pattern<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &(b, m)) in self.bytes.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, " "));
+            }
+
+            for capture in self.captures.iter().filter(|capture| capture.offset == i) {
+                try!(write!(f, "@{} ", capture.name));
+            }
+
+            try!(write_byte(f, b, m));
+        }
+
+        Ok(())
+    }
+}
+
+fn write_byte(f: &mut fmt::Formatter, b: u8, m: u8) -> fmt::Result {
+    match m {
+        0xFF => write!(f, "{:02X}", b),
+        0x00 => write!(f, "??"),
+        0xF0 => write!(f, "{:X}?", b >> 4),
+        0x0F => write!(f, "?{:X}", b & 0xF),
+        _ => {
+            try!(write!(f, "["));
+
+            for bit in (0..8).rev() {
+                if bit == 3 {
+                    try!(write!(f, " "));
+                }
+
+                if m & (1 << bit) == 0 {
+                    try!(write!(f, "?"));
+                } else if b & (1 << bit) == 0 {
+                    try!(write!(f, "0"));
+                } else {
+                    try!(write!(f, "1"));
+                }
+            }
+
+            write!(f, "]")
+        }
+    }
+}
+
+// Parses a single byte: `8B`, `0x8B`, `??`, a byte with wildcard nibbles like `8?`, or a bit
+// pattern like `[1?11 0???]`.
+fn parse_byte(token: &str) -> Option<(u8, u8)> {
+    if token == "??" || token == "?" {
+        return Some((0x00, 0x00));
+    }
+
+    if token.starts_with('[') && token.ends_with(']') {
+        let bits = token[1..token.len() - 1]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<char>>();
+
+        if bits.len() != 8 {
+            return None;
+        }
+
+        let mut b = 0;
+        let mut m = 0;
+
+        for c in bits {
+            b <<= 1;
+            m <<= 1;
+
+            match c {
+                '0' => m |= 1,
+                '1' => {
+                    b |= 1;
+                    m |= 1;
+                }
+                '?' => {}
+                _ => return None,
+            }
+        }
+
+        return Some((b, m));
+    }
+
+    let digits = if token.starts_with("0x") || token.starts_with("0X") {
+        &token[2..]
+    } else {
+        token
+    };
+
+    let nibbles = digits.chars().collect::<Vec<char>>();
+
+    if nibbles.len() != 2 {
+        return None;
+    }
+
+    let mut b = 0;
+    let mut m = 0;
+
+    for c in nibbles {
+        b <<= 4;
+        m <<= 4;
+
+        if c != '?' {
+            match c.to_digit(16) {
+                Some(digit) => b |= digit as u8,
+                None => return None,
+            }
+
+            m |= 0xF;
         }
+    }
+
+    Some((b, m))
+}
+
+/// A pattern that is parsed at runtime from an IDA-style string like `"8B 54 24 04 ?? F2 AE"`.
+/// Besides whole-byte wildcards it accepts nibble wildcards (`8?`) and bit patterns
+/// (`[1?11 0???]`), and `@name` before a byte starts a named capture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedPattern {
+    pub bytes: Vec<(u8, u8)>,
+    pub captures: Vec<Capture>,
+}
+
+impl OwnedPattern {
+    pub fn parse(string: &str) -> Result<OwnedPattern, ParseError> {
+        let mut bytes = Vec::new();
+        let mut captures = Vec::new();
+
+        let mut tokens = string.split_whitespace().enumerate();
+
+        while let Some((index, token)) = tokens.next() {
+            if token.starts_with('@') {
+                let name = &token[1..];
+
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(ParseError::InvalidToken(index, token.to_owned()));
+                }
+
+                if captures.iter().any(|capture: &Capture| capture.name == name) {
+                    return Err(ParseError::DuplicateCapture(name.to_owned()));
+                }
+
+                captures.push(Capture {
+                    name: Cow::Owned(name.to_owned()),
+                    offset: bytes.len(),
+                });
+                continue;
+            }
+
+            // Bit patterns may contain whitespace, collect the rest of them.
+            let mut token = token.to_owned();
+            if token.starts_with('[') {
+                while !token.ends_with(']') {
+                    match tokens.next() {
+                        Some((_, next)) => {
+                            token.push(' ');
+                            token.push_str(next);
+                        }
+                        None => return Err(ParseError::InvalidToken(index, token)),
+                    }
+                }
+            }
+
+            match parse_byte(&token) {
+                Some(byte) => bytes.push(byte),
+                None => return Err(ParseError::InvalidToken(index, token)),
+            }
+        }
+
+        if bytes.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        if bytes.iter().all(|&(_, m)| m == 0) {
+            return Err(ParseError::OnlyWildcards);
+        }
+
+        if let Some(capture) = captures.iter().find(|capture| capture.offset == bytes.len()) {
+            return Err(ParseError::CaptureAtEnd(capture.name.clone().into_owned()));
+        }
+
+        Ok(OwnedPattern {
+            bytes: bytes,
+            captures: captures,
+        })
+    }
+
+    pub fn as_pattern<'a>(&'a self) -> Pattern<'a> {
+        Pattern {
+            bytes: &self.bytes,
+            captures: &self.captures,
+        }
+    }
+}
+
+impl FromStr for OwnedPattern {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OwnedPattern::parse(s)
+    }
+}
+
+impl fmt::Display for OwnedPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_pattern().fmt(f)
+    }
+}
+
+impl<'a> From<&'a OwnedPattern> for Pattern<'a> {
+    fn from(pattern: &'a OwnedPattern) -> Self {
+        pattern.as_pattern()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    OnlyWildcards,
+    InvalidToken(usize, String),
+    DuplicateCapture(String),
+    CaptureAtEnd(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "the pattern is empty"),
+            ParseError::OnlyWildcards => write!(f, "the pattern consists only of wildcards"),
+            ParseError::InvalidToken(index, ref token) => {
+                write!(f, "invalid token \"{}\" at position {}", token, index + 1)
+            }
+            ParseError::DuplicateCapture(ref name) => write!(f, "duplicate capture \"@{}\"", name),
+            ParseError::CaptureAtEnd(ref name) => {
+                write!(f, "capture \"@{}\" is not followed by any bytes", name)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::Empty => "the pattern is empty",
+            ParseError::OnlyWildcards => "the pattern consists only of wildcards",
+            ParseError::InvalidToken(..) => "invalid token",
+            ParseError::DuplicateCapture(_) => "duplicate capture",
+            ParseError::CaptureAtEnd(_) => "capture is not followed by any bytes",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn parse(string: &str) -> OwnedPattern {
+        OwnedPattern::parse(string).unwrap()
+    }
+
+    // Compares the pattern at every offset, like the scanner did before anchoring.
+    fn naive_find_all(pattern: Pattern, data: &[u8]) -> Vec<usize> {
+        if data.len() < pattern.len() {
+            return Vec::new();
+        }
+
+        (0..data.len() - pattern.len() + 1).filter(|&i| pattern.compare(&data[i..])).collect()
+    }
+
+    // Bytes with roughly the distribution of x86 code: mostly common opcode and ModRM bytes.
+    fn synthetic_code(len: usize) -> Vec<u8> {
+        const COMMON: &'static [u8] = &[0x00, 0xFF, 0x8B, 0x89, 0x24, 0x04, 0x08, 0x44, 0x45,
+                                        0x83, 0x85, 0x8D, 0x50, 0x55, 0x56, 0x5E, 0xC3, 0xE8];
+        let mut state = 0x1234_5678u32;
+
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let r = (state >> 16) as usize;
+
+                if r % 4 == 0 {
+                    (r >> 2) as u8
+                } else {
+                    COMMON[(r >> 2) % COMMON.len()]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_at_last_valid_offset() {
+        let pattern = parse("8B 54 24");
+        let data = [0x90, 0x90, 0x90, 0x8B, 0x54, 0x24];
+
+        assert_eq!(pattern.as_pattern().find_in(&data), Some(3));
+        assert_eq!(pattern.as_pattern().find_in(&data[..5]), None);
+        assert_eq!(pattern.as_pattern().find_in(&data[3..]), Some(0));
+    }
+
+    #[test]
+    fn find_with_anchor_after_start() {
+        // 0x8B is more common than 0xA1, so the anchor is the second byte.
+        let pattern = parse("8B A1 ?? 04");
+        assert_eq!(pattern.as_pattern().anchor(), Some((1, 0xA1)));
+
+        // An 0xA1 at the very start can't be the anchor of a match.
+        let data = [0xA1, 0x8B, 0x8B, 0xA1, 0x00, 0x05, 0x8B, 0xA1, 0x00, 0x04];
+        assert_eq!(pattern.as_pattern().find_all_in(&data), vec![6]);
+    }
+
+    #[test]
+    fn find_with_wildcard_prefix() {
+        let pattern = parse("?? ?? ?? A1");
+        assert_eq!(pattern.as_pattern().anchor(), Some((3, 0xA1)));
+
+        let data = [0xA1, 0xA1, 0x00, 0x00, 0xA1, 0xA1];
+        assert_eq!(pattern.as_pattern().find_all_in(&data), vec![1, 2]);
+        assert_eq!(pattern.as_pattern().find_all_in(&data[..3]), Vec::<usize>::new());
+
+        // Without a fully matched byte every offset is compared.
+        let pattern = parse("?? ?? A?");
+        assert_eq!(pattern.as_pattern().anchor(), None);
+        assert_eq!(pattern.as_pattern().find_all_in(&data), vec![2, 3]);
+    }
+
+    #[test]
+    fn find_matches_naive_scan() {
+        let data = synthetic_code(64 * 1024);
+
+        for string in &["8B 44 24 04", "?? 8B ?? 24", "E8 ?? ?? ?? ?? 83 C4", "5? [1?00 0101]"] {
+            let pattern = parse(string);
+            let pattern = pattern.as_pattern();
+
+            let expected = naive_find_all(pattern, &data);
+            assert_eq!(pattern.find_all_in(&data), expected, "{}", string);
+            assert_eq!(PatternSet::new(&[pattern]).find_all_in(&data), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn pattern_set_shared_anchor() {
+        let strings = ["A1 ?? 8B", "8B A1", "A1 A1", "?? A1 00"];
+        let patterns = strings.iter().map(|string| parse(string)).collect::<Vec<_>>();
+        let patterns = patterns.iter().map(|pattern| pattern.as_pattern()).collect::<Vec<_>>();
+
+        for pattern in &patterns {
+            assert_eq!(pattern.anchor().map(|(_, b)| b), Some(0xA1), "{}", pattern);
+        }
+
+        let data = [0xA1, 0xA1, 0x8B, 0xA1, 0x00, 0x8B, 0xA1, 0xA1, 0x00, 0xA1];
+        let expected = patterns.iter()
+                               .map(|&pattern| naive_find_all(pattern, &data))
+                               .collect::<Vec<_>>();
+        assert_eq!(PatternSet::new(&patterns).find_all_in(&data), expected);
+        assert_eq!(expected, vec![vec![0, 3], vec![2, 5], vec![0, 6], vec![2, 6]]);
+    }
+
+    #[test]
+    fn pattern_set_duplicates_and_empty() {
+        let a = parse("8B ?? 04");
+        let empty = Pattern {
+            bytes: &[],
+            captures: &[],
+        };
+        let patterns = [a.as_pattern(), empty, a.as_pattern()];
+
+        let data = [0x8B, 0x44, 0x04, 0x8B, 0x4C, 0x04];
+        assert_eq!(PatternSet::new(&patterns).find_all_in(&data),
+                   vec![vec![0, 3], vec![], vec![0, 3]]);
+    }
+
+    // cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_anchored_scan() {
+        let mut data = synthetic_code(32 * 1024 * 1024);
+        let pattern = parse("8B 0D ?? ?? ?? ?? 85 C9 74 ?? 8B 01 FF 50");
+        let pattern = pattern.as_pattern();
+
+        let last = data.len() - pattern.len();
+        for (i, &(b, _)) in pattern.bytes.iter().enumerate() {
+            data[last + i] = b;
+        }
+
+        let start = Instant::now();
+        let naive = naive_find_all(pattern, &data);
+        let naive_time = start.elapsed();
+
+        let start = Instant::now();
+        let anchored = pattern.find_all_in(&data);
+        let anchored_time = start.elapsed();
+
+        assert_eq!(anchored, naive);
+        assert_eq!(anchored.last(), Some(&last));
+
+        println!("{} MiB: naive {:?}, anchored {:?}",
+                 data.len() / 1024 / 1024,
+                 naive_time,
+                 anchored_time);
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let pattern = OwnedPattern::parse("8B 54 24 04 ?? @addr ?? F2 0xAE").unwrap();
+
+        assert_eq!(pattern.bytes,
+                   vec![(0x8B, 0xFF), (0x54, 0xFF), (0x24, 0xFF), (0x04, 0xFF), (0x00, 0x00),
+                        (0x00, 0x00), (0xF2, 0xFF), (0xAE, 0xFF)]);
+        assert_eq!(pattern.captures,
+                   vec![Capture {
+                            name: Cow::Borrowed("addr"),
+                            offset: 5,
+                        }]);
+
+        let string = pattern.to_string();
+        assert_eq!(string, "8B 54 24 04 ?? @addr ?? F2 AE");
+        assert_eq!(string.parse::<OwnedPattern>(), Ok(pattern));
+    }
+
+    #[test]
+    fn parse_masks() {
+        let pattern = parse("8? ?B 0x8? [1?11 0???] [0000 1111] [1?110???]");
+
+        assert_eq!(pattern.bytes,
+                   vec![(0x80, 0xF0), (0x0B, 0x0F), (0x80, 0xF0), (0xB0, 0xB8), (0x0F, 0xFF),
+                        (0xB0, 0xB8)]);
+        assert_eq!(pattern.to_string(), "8? ?B 8? [1?11 0???] 0F [1?11 0???]");
+    }
+
+    pattern!(MASKED (0x8B & 0xF0) (0xB7 & 0xB8) 0x4C);
+
+    #[test]
+    fn parse_masks_like_the_macro() {
+        assert_eq!(parse("8? [1?11 0???] 4C").bytes, MASKED.bytes);
+    }
+
+    #[test]
+    fn parse_invalid_masks() {
+        assert_eq!(OwnedPattern::parse("8B [1?1]"),
+                   Err(ParseError::InvalidToken(1, "[1?1]".to_owned())));
+        assert_eq!(OwnedPattern::parse("[1?11 0??2]"),
+                   Err(ParseError::InvalidToken(0, "[1?11 0??2]".to_owned())));
+        assert_eq!(OwnedPattern::parse("[1?11 0???0]"),
+                   Err(ParseError::InvalidToken(0, "[1?11 0???0]".to_owned())));
+        assert_eq!(OwnedPattern::parse("8B [1?11"),
+                   Err(ParseError::InvalidToken(1, "[1?11".to_owned())));
+        assert_eq!(OwnedPattern::parse("8??"), Err(ParseError::InvalidToken(0, "8??".to_owned())));
+        assert_eq!(OwnedPattern::parse("G?"), Err(ParseError::InvalidToken(0, "G?".to_owned())));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(OwnedPattern::parse(""), Err(ParseError::Empty));
+        assert_eq!(OwnedPattern::parse("  @addr "), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn parse_only_wildcards() {
+        assert_eq!(OwnedPattern::parse("?? ? ??"), Err(ParseError::OnlyWildcards));
+    }
+
+    #[test]
+    fn parse_invalid_token() {
+        assert_eq!(OwnedPattern::parse("8B 5G 24"),
+                   Err(ParseError::InvalidToken(1, "5G".to_owned())));
+        assert_eq!(OwnedPattern::parse("8B 544"),
+                   Err(ParseError::InvalidToken(1, "544".to_owned())));
+        assert_eq!(OwnedPattern::parse("8B @ 54"),
+                   Err(ParseError::InvalidToken(1, "@".to_owned())));
+        assert_eq!(OwnedPattern::parse("8B @a-b 54"),
+                   Err(ParseError::InvalidToken(1, "@a-b".to_owned())));
+    }
+
+    #[test]
+    fn parse_duplicate_capture() {
+        assert_eq!(OwnedPattern::parse("@a 8B @a 54"),
+                   Err(ParseError::DuplicateCapture("a".to_owned())));
+    }
 
-        true
+    #[test]
+    fn parse_capture_at_end() {
+        assert_eq!(OwnedPattern::parse("8B 54 @end"),
+                   Err(ParseError::CaptureAtEnd("end".to_owned())));
     }
 }
@@ -0,0 +1,432 @@
+// What happens to the entries when modules are loaded and unloaded. Kernel32 calls these with
+// the entries in dependency order and refreshes the features when something changed.
+
+use hookable::Hookable;
+use moduleinfo::ModuleInfo;
+use moduleinfo::provider::ModuleProvider;
+use std::sync::RwLock;
+use winapi::*;
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub name: &'static str,
+    pub hookable: &'static RwLock<Hookable>,
+    pub dependencies: &'static [&'static str],
+}
+
+impl Entry {
+    pub fn is_hooked(&self) -> bool {
+        self.hookable.read().unwrap().module_info().is_some()
+    }
+}
+
+// Every entry after its dependencies, otherwise in the given order. Entries with unknown or
+// circular dependencies can never be hooked and are left out.
+pub fn order(mut remaining: Vec<Entry>) -> Vec<Entry> {
+    let mut rv: Vec<Entry> = Vec::with_capacity(remaining.len());
+
+    loop {
+        let next = remaining.iter().position(|entry| {
+            entry.dependencies
+                 .iter()
+                 .all(|dependency| rv.iter().any(|placed| placed.name == *dependency))
+        });
+
+        match next {
+            Some(index) => rv.push(remaining.remove(index)),
+            None => break,
+        }
+    }
+
+    for entry in remaining {
+        warn!(target: "registry",
+              "{} has unknown or circular dependencies: {}",
+              entry.name,
+              entry.dependencies.join(", "));
+    }
+
+    rv
+}
+
+// The ordered entries have the dependencies of every one of them.
+pub fn dependencies_hooked(ordered: &[Entry], entry: &Entry) -> bool {
+    entry.dependencies.iter().all(|&dependency| {
+        ordered.iter()
+               .find(|other| other.name == dependency)
+               .map_or(false, |dependency| dependency.is_hooked())
+    })
+}
+
+// Hooks every entry in the best of the loaded modules. Returns whether anything was hooked.
+pub fn hook_loaded(ordered: &[Entry], provider: &ModuleProvider) -> bool {
+    let modules = provider.get_loaded();
+
+    let mut hooked_something = false;
+
+    for entry in ordered {
+        if !dependencies_hooked(ordered, entry) {
+            debug!(target: "registry",
+                   "Not hooking {}, its dependencies aren't hooked",
+                   entry.name);
+            continue;
+        }
+
+        let target = entry.hookable
+                          .read()
+                          .unwrap()
+                          .pick_best_hook_target(provider, &modules)
+                          .cloned();
+
+        if let Some(module) = target {
+            entry.hookable.write().unwrap().hook(&module);
+            hooked_something = true;
+        }
+    }
+
+    hooked_something
+}
+
+// Hooks whatever wants the module, then re-hooks everything that depends on what got hooked,
+// since it was set up with the previous instance of its dependency or not at all. Returns whether
+// anything was hooked.
+pub fn hook_module(ordered: &[Entry], provider: &ModuleProvider, handle: HMODULE) -> bool {
+    let module = match provider.get_module_info(handle) {
+        Some(module) => module,
+        None => return false,
+    };
+
+    let mut hooked: Vec<&'static str> = Vec::new();
+    let mut modules = None;
+
+    for entry in ordered {
+        let target = if entry.hookable.read().unwrap().should_hook(provider, &module) {
+            Some(module)
+        } else if entry.dependencies.iter().any(|dependency| hooked.contains(dependency)) {
+            let hook = entry.hookable.read().unwrap();
+
+            let target = match hook.module_info() {
+                Some(module_info) => Some(*module_info),
+                None => {
+                    if modules.is_none() {
+                        modules = Some(provider.get_loaded());
+                    }
+
+                    hook.pick_best_hook_target(provider, modules.as_ref().unwrap()).cloned()
+                }
+            };
+
+            target
+        } else {
+            None
+        };
+
+        let target = match target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        if !dependencies_hooked(ordered, entry) {
+            debug!(target: "registry",
+                   "Not hooking {}, its dependencies aren't hooked",
+                   entry.name);
+            continue;
+        }
+
+        rehook(entry, &target);
+        hooked.push(entry.name);
+    }
+
+    !hooked.is_empty()
+}
+
+// Unhooks the entries that use the module, and everything that depends on them before them.
+// Returns whether anything was unhooked.
+pub fn unhook_module(ordered: &[Entry], handle: HMODULE) -> bool {
+    let mut affected: Vec<&'static str> = Vec::new();
+
+    for entry in ordered {
+        let uses_module = entry.hookable
+                               .read()
+                               .unwrap()
+                               .module_info()
+                               .map_or(false, |module_info| module_info.handle == handle);
+
+        if uses_module ||
+           entry.dependencies.iter().any(|dependency| affected.contains(dependency)) {
+            affected.push(entry.name);
+        }
+    }
+
+    let mut unhooked_something = false;
+
+    for entry in ordered.iter().rev().filter(|entry| affected.contains(&entry.name)) {
+        if entry.is_hooked() {
+            entry.hookable.write().unwrap().unhook();
+            unhooked_something = true;
+        }
+    }
+
+    unhooked_something
+}
+
+fn rehook(entry: &Entry, module: &ModuleInfo) {
+    let mut hook = entry.hookable.write().unwrap();
+
+    if hook.module_info().is_some() {
+        hook.unhook();
+    }
+
+    hook.hook(module);
+}
+
+#[cfg(test)]
+mod tests {
+    use matcher::{HookableOrderedNameFilter, ModuleMatcher};
+    use moduleinfo::provider::Fake;
+    use status::FunctionStatus;
+    use std::cell::RefCell;
+    use super::*;
+
+    thread_local! {
+        // The targets look their modules up here, like the hookables do in WINDOWS.
+        static PROVIDER: RefCell<Fake> = RefCell::new(Fake::new());
+        // What the targets did, in order.
+        static EVENTS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    }
+
+    struct Target {
+        name: &'static str,
+        matchers: &'static [ModuleMatcher],
+        module_info: Option<ModuleInfo>,
+        current_name_index: Option<usize>,
+    }
+
+    impl HookableOrderedNameFilter for Target {
+        fn get_current_name_index(&self) -> Option<usize> {
+            self.current_name_index
+        }
+
+        fn get_matchers(&self) -> &[ModuleMatcher] {
+            self.matchers
+        }
+    }
+
+    impl Hookable for Target {
+        fn module_info(&self) -> Option<&ModuleInfo> {
+            self.module_info.as_ref()
+        }
+
+        fn functions(&self) -> &[FunctionStatus] {
+            &[]
+        }
+
+        fn hook(&mut self, module_info: &ModuleInfo) {
+            self.module_info = Some(*module_info);
+            self.current_name_index = PROVIDER.with(|provider| {
+                self.compute_current_name_index(&*provider.borrow(), module_info)
+            });
+            event(format!("hook {}", self.name));
+        }
+
+        fn unhook(&mut self) {
+            self.module_info = None;
+            self.current_name_index = None;
+            event(format!("unhook {}", self.name));
+        }
+
+        fn set_hook_enabled(&mut self, _name: &str, _enabled: bool) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn event(event: String) {
+        EVENTS.with(|events| events.borrow_mut().push(event));
+    }
+
+    fn take_events() -> Vec<String> {
+        EVENTS.with(|events| events.borrow_mut().drain(..).collect())
+    }
+
+    fn entry(name: &'static str,
+             matchers: &'static [ModuleMatcher],
+             dependencies: &'static [&'static str])
+             -> Entry {
+        let target = Target {
+            name: name,
+            matchers: matchers,
+            module_info: None,
+            current_name_index: None,
+        };
+
+        Entry {
+            name: name,
+            hookable: Box::leak(Box::new(RwLock::new(target))),
+            dependencies: dependencies,
+        }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&'static str> {
+        entries.iter().map(|entry| entry.name).collect()
+    }
+
+    fn hooked_handle(entry: &Entry) -> Option<HMODULE> {
+        entry.hookable.read().unwrap().module_info().map(|module_info| module_info.handle)
+    }
+
+    fn should_hook(entry: &Entry, module: &ModuleInfo) -> bool {
+        PROVIDER.with(|provider| {
+            entry.hookable.read().unwrap().should_hook(&*provider.borrow(), module)
+        })
+    }
+
+    // What Kernel32 does in LoadLibrary().
+    fn load_library(ordered: &[Entry], path: &str) -> (ModuleInfo, bool) {
+        PROVIDER.with(|provider| {
+            let module = provider.borrow_mut().add(path, Vec::new());
+            (module, hook_module(ordered, &*provider.borrow(), module.handle))
+        })
+    }
+
+    // What Kernel32 does in FreeLibrary().
+    fn free_library(ordered: &[Entry], handle: HMODULE) -> bool {
+        let rv = unhook_module(ordered, handle);
+        PROVIDER.with(|provider| provider.borrow_mut().remove(handle));
+        rv
+    }
+
+    const ENGINE: &'static [ModuleMatcher] = &[
+        ModuleMatcher::Name("engine.dll"),
+        ModuleMatcher::Glob("engine*.dll"),
+    ];
+    const CLIENT: &'static [ModuleMatcher] = &[ModuleMatcher::Name("client.dll")];
+    const SERVER: &'static [ModuleMatcher] = &[ModuleMatcher::Name("server.dll")];
+
+    #[test]
+    fn order_puts_dependencies_first() {
+        let entries = vec![entry("c", &[], &["b"]),
+                           entry("b", &[], &["a"]),
+                           entry("a", &[], &[]),
+                           entry("d", &[], &[]),
+                           entry("e", &[], &["a", "d"])];
+
+        assert_eq!(names(&order(entries)), ["a", "b", "c", "d", "e"]);
+        assert!(order(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn order_leaves_out_cycles_and_unknown_dependencies() {
+        let entries = vec![entry("x", &[], &["y"]),
+                           entry("y", &[], &["x"]),
+                           entry("self", &[], &["self"]),
+                           entry("missing", &[], &["nothing"]),
+                           // Depends on something that is left out.
+                           entry("after", &[], &["missing"]),
+                           entry("ok", &[], &[])];
+
+        assert_eq!(names(&order(entries)), ["ok"]);
+    }
+
+    #[test]
+    fn hook_loaded_waits_for_dependencies() {
+        let ordered = order(vec![entry("client", CLIENT, &["engine"]),
+                                 entry("engine", ENGINE, &[])]);
+
+        PROVIDER.with(|provider| provider.borrow_mut().add("C:/hl2/bin/client.dll", Vec::new()));
+        let hooked = PROVIDER.with(|provider| hook_loaded(&ordered, &*provider.borrow()));
+        assert!(!hooked);
+        assert!(take_events().is_empty());
+
+        // Loading the dependency hooks the already loaded dependent too.
+        let (engine, hooked) = load_library(&ordered, "C:/hl2/bin/engine.dll");
+        assert!(hooked);
+        assert_eq!(take_events(), ["hook engine", "hook client"]);
+        assert_eq!(hooked_handle(&ordered[0]), Some(engine.handle));
+        assert!(ordered[1].is_hooked());
+
+        // Nothing wants this one.
+        let (_, hooked) = load_library(&ordered, "C:/hl2/bin/server.dll");
+        assert!(!hooked);
+        assert!(take_events().is_empty());
+    }
+
+    #[test]
+    fn better_dependency_rehooks_dependents() {
+        let ordered = order(vec![entry("engine", ENGINE, &[]),
+                                 entry("client", CLIENT, &["engine"]),
+                                 entry("server", SERVER, &[])]);
+
+        let (worse, _) = load_library(&ordered, "C:/hl2/bin/engine_srv.dll");
+        let (client, _) = load_library(&ordered, "C:/hl2/bin/client.dll");
+        let (server, _) = load_library(&ordered, "C:/hl2/bin/server.dll");
+        assert_eq!(take_events(), ["hook engine", "hook client", "hook server"]);
+        assert_eq!(hooked_handle(&ordered[0]), Some(worse.handle));
+
+        // The client is hooked again in the module it already uses, the server is left alone.
+        let (better, hooked) = load_library(&ordered, "C:/hl2/bin/engine.dll");
+        assert!(hooked);
+        assert_eq!(take_events(),
+                   ["unhook engine", "hook engine", "unhook client", "hook client"]);
+        assert_eq!(hooked_handle(&ordered[0]), Some(better.handle));
+        assert_eq!(hooked_handle(&ordered[1]), Some(client.handle));
+        assert_eq!(hooked_handle(&ordered[2]), Some(server.handle));
+
+        // Nothing beats the best match.
+        let (_, hooked) = load_library(&ordered, "D:/engine_other.dll");
+        assert!(!hooked);
+        assert!(take_events().is_empty());
+    }
+
+    #[test]
+    fn unload_unhooks_dependents_first() {
+        let ordered = order(vec![entry("engine", ENGINE, &[]),
+                                 entry("client", CLIENT, &["engine"]),
+                                 entry("server", SERVER, &[])]);
+
+        let (engine, _) = load_library(&ordered, "C:/hl2/bin/engine.dll");
+        load_library(&ordered, "C:/hl2/bin/client.dll");
+        load_library(&ordered, "C:/hl2/bin/server.dll");
+        let (other, _) = load_library(&ordered, "C:/hl2/bin/other.dll");
+        take_events();
+
+        assert!(!free_library(&ordered, other.handle));
+        assert!(take_events().is_empty());
+
+        assert!(free_library(&ordered, engine.handle));
+        assert_eq!(take_events(), ["unhook client", "unhook engine"]);
+        assert!(!ordered[0].is_hooked());
+        assert!(!ordered[1].is_hooked());
+        assert!(ordered[2].is_hooked());
+
+        // Both come back with the engine.
+        load_library(&ordered, "C:/hl2/bin/engine.dll");
+        assert_eq!(take_events(), ["hook engine", "hook client"]);
+    }
+
+    #[test]
+    fn unload_lets_worse_match_hook() {
+        const SERVER_PATHS: &'static [ModuleMatcher] = &[
+            ModuleMatcher::PathSuffix("hl2/bin/server.dll"),
+            ModuleMatcher::Name("server.dll"),
+        ];
+        let ordered = order(vec![entry("server", SERVER_PATHS, &[])]);
+
+        let (worse, _) = load_library(&ordered, "C:/Games/mod/bin/server.dll");
+        let (better, _) = load_library(&ordered, "C:/Games/hl2/bin/server.dll");
+        assert_eq!(hooked_handle(&ordered[0]), Some(better.handle));
+        assert!(!should_hook(&ordered[0], &worse));
+
+        // Unloading a module that isn't hooked changes nothing.
+        let (client, _) = load_library(&ordered, "C:/Games/hl2/bin/client.dll");
+        assert!(!free_library(&ordered, client.handle));
+        assert_eq!(hooked_handle(&ordered[0]), Some(better.handle));
+
+        assert!(free_library(&ordered, better.handle));
+        assert!(!ordered[0].is_hooked());
+        assert_eq!(take_events(), ["hook server", "unhook server", "hook server", "unhook server"]);
+
+        // The worse match is good enough again.
+        assert!(should_hook(&ordered[0], &worse));
+        PROVIDER.with(|provider| assert!(hook_loaded(&ordered, &*provider.borrow())));
+        assert_eq!(hooked_handle(&ordered[0]), Some(worse.handle));
+    }
+}
@@ -0,0 +1,44 @@
+// The hookables, by name, with the names of the hookables they need to be hooked first. Kernel32
+// walks them in dependency order when modules are loaded and unloaded.
+
+use hookable::Hookable;
+use std::sync::RwLock;
+
+// Walking the entries doesn't depend on Windows so that the offline tools can test it.
+mod hooking;
+pub use self::hooking::*;
+
+lazy_static! {
+    static ref ENTRIES: RwLock<Vec<Entry>> = RwLock::new(Vec::new());
+}
+
+pub fn register(name: &'static str,
+                hookable: &'static RwLock<Hookable>,
+                dependencies: &'static [&'static str]) {
+    let mut entries = ENTRIES.write().unwrap();
+
+    if entries.iter().any(|entry| entry.name == name) {
+        warn!(target: "registry", "{} is already registered", name);
+        return;
+    }
+
+    entries.push(Entry {
+        name: name,
+        hookable: hookable,
+        dependencies: dependencies,
+    });
+}
+
+// In the registration order.
+pub fn entries() -> Vec<Entry> {
+    ENTRIES.read().unwrap().clone()
+}
+
+pub fn get(name: &str) -> Option<Entry> {
+    ENTRIES.read().unwrap().iter().find(|entry| entry.name == name).cloned()
+}
+
+// See order().
+pub fn ordered() -> Vec<Entry> {
+    order(entries())
+}
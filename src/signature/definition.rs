@@ -0,0 +1,118 @@
+use moduleinfo::pe::{Layout, Pe};
+use moduleinfo::scan::{self, Scope};
+use moduleinfo::xref;
+use pattern::{FindError, Pattern};
+use std::fmt;
+
+// A function that can be found with one of several patterns, one per game build. Every variant
+// carries the offsets that only make sense together with that exact build. A build only gets a
+// variant once its binaries have been checked with sigcheck, so far that's only HL:S OOE.
+//
+// If none of the patterns match, the function can still be located through a string it
+// references, such as an error message, as long as no other function references it.
+pub struct Signature<O: 'static = ()> {
+    pub name: &'static str,
+    pub variants: &'static [Variant<O>],
+    pub string_ref: Option<&'static str>,
+}
+
+pub struct Variant<O: 'static = ()> {
+    pub name: &'static str,
+    pub pattern: Pattern<'static>,
+    pub offsets: O,
+}
+
+pub enum Method<O: 'static = ()> {
+    Pattern(&'static Variant<O>),
+    // The address came from the cache and was verified against the pattern of this variant.
+    Cached(&'static Variant<O>),
+    StringReference(&'static str),
+}
+
+impl<O> Clone for Method<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O> Copy for Method<O> {}
+
+impl<O> fmt::Display for Method<O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Pattern(variant) => write!(f, "pattern, {}", variant.name),
+            Method::Cached(variant) => write!(f, "cache, {}", variant.name),
+            Method::StringReference(string) => write!(f, "string reference, {:?}", string),
+        }
+    }
+}
+
+impl<O> Signature<O> {
+    // The first variant that matches exactly one location, falling back to the string reference.
+    // The DLL and the offline tools both look signatures up this way, only where the matches come
+    // from differs.
+    pub fn lookup<F, G>(&self,
+                        mut find_unique: F,
+                        find_by_string: G)
+                        -> Result<(usize, Method<O>), FindError>
+        where F: FnMut(&'static Variant<O>) -> Result<usize, FindError>,
+              G: FnOnce(&'static str) -> Result<usize, FindError>
+    {
+        let mut error = FindError::NotFound;
+
+        for variant in self.variants {
+            match find_unique(variant) {
+                Ok(address) => return Ok((address, Method::Pattern(variant))),
+                Err(err @ FindError::Ambiguous(_)) => error = err,
+                Err(FindError::NotFound) => {}
+            }
+        }
+
+        if let Some(string) = self.string_ref {
+            match find_by_string(string) {
+                Ok(address) => return Ok((address, Method::StringReference(string))),
+                Err(err @ FindError::Ambiguous(_)) => error = err,
+                Err(FindError::NotFound) => {}
+            }
+        }
+
+        Err(error)
+    }
+}
+
+// Object-safe view of a signature regardless of its offsets type, for code that only needs the
+// patterns, such as the offline signature checker.
+pub trait AnySignature: Sync {
+    fn name(&self) -> &'static str;
+    fn patterns(&self) -> Vec<(&'static str, Pattern<'static>)>;
+    fn string_ref(&self) -> Option<&'static str>;
+    // Looks the signature up in a mapped image, see Signature::lookup(). Returns the RVA and how
+    // it was found.
+    fn find_in_image(&self, image: &[u8]) -> Result<(usize, String), FindError>;
+}
+
+impl<O: Sync> AnySignature for Signature<O> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn patterns(&self) -> Vec<(&'static str, Pattern<'static>)> {
+        self.variants.iter().map(|variant| (variant.name, variant.pattern)).collect()
+    }
+
+    fn string_ref(&self) -> Option<&'static str> {
+        self.string_ref
+    }
+
+    fn find_in_image(&self, image: &[u8]) -> Result<(usize, String), FindError> {
+        self.lookup(|variant| scan::find_unique_in(image, Scope::Executable, variant.pattern),
+                    |string| {
+                        Pe::parse(image, Layout::Image)
+                            .map_err(|_| FindError::NotFound)
+                            .and_then(|pe| {
+                                xref::find_function_by_string(&pe, pe.image_base as usize, string)
+                            })
+                    })
+            .map(|(rva, method)| (rva, method.to_string()))
+    }
+}
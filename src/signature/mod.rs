@@ -0,0 +1,206 @@
+use cache;
+use moduleinfo::{AddressResult, FindError, ModuleInfo};
+use pattern::Pattern;
+use std::ptr;
+use std::collections::HashMap;
+use winapi::*;
+
+// The definitions don't depend on Windows so that the offline tools can share them.
+mod definition;
+pub use self::definition::*;
+
+pub struct Found<O: 'static = ()> {
+    pub address: LPVOID,
+    pub method: Method<O>,
+}
+
+impl<O> Clone for Found<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O> Copy for Found<O> {}
+
+impl<O> Found<O> {
+    // The matched variant. Offsets and captures are only known when a pattern was used.
+    pub fn variant(&self) -> Option<&'static Variant<O>> {
+        match self.method {
+            Method::Pattern(variant) | Method::Cached(variant) => Some(variant),
+            Method::StringReference(_) => None,
+        }
+    }
+
+    pub fn capture(&self, name: &str) -> Option<*mut u8> {
+        self.variant()
+            .and_then(|variant| variant.pattern.capture_offset(name))
+            .map(|offset| unsafe { (self.address as *mut u8).offset(offset as isize) })
+    }
+
+    // The absolute imm32 at the capture, e.g. the address operand of `mov al, [addr]`.
+    pub fn imm32(&self, name: &str) -> Option<usize> {
+        self.capture(name).map(|p| unsafe { ptr::read_unaligned(p as *const u32) as usize })
+    }
+
+    // The destination of an `E8`/`E9` rel32 call or jump whose operand is at the capture.
+    pub fn rel32_target(&self, name: &str) -> Option<LPVOID> {
+        self.capture(name).map(|p| unsafe {
+            let rel = ptr::read_unaligned(p as *const i32);
+            p.offset(4).offset(rel as isize) as LPVOID
+        })
+    }
+
+    // The pointer stored at the absolute address that is captured.
+    pub fn deref(&self, name: &str) -> Option<LPVOID> {
+        self.imm32(name).map(|addr| unsafe { ptr::read_unaligned(addr as *const LPVOID) })
+    }
+}
+
+// The pattern matches of several signatures, found with a single pass over the module. Signatures
+// that have a valid cache entry aren't scanned for.
+pub struct Batch<'m> {
+    module_info: &'m ModuleInfo,
+    key: Option<cache::ModuleKey>,
+    // Keyed by the signature and the variant name.
+    matches: HashMap<(&'static str, &'static str), Vec<LPVOID>>,
+}
+
+impl<'m> Batch<'m> {
+    pub fn new(module_info: &'m ModuleInfo, signatures: &[&AnySignature]) -> Batch<'m> {
+        let key = cache::module_key(module_info);
+
+        let mut patterns = Vec::new();
+
+        for signature in signatures {
+            let variants = signature.patterns();
+
+            let cached = key.as_ref()
+                            .and_then(|key| cache::lookup(key, signature.name()))
+                            .map_or(false, |(rva, variant_name)| {
+                                variants.iter().any(|&(name, pattern)| {
+                                    name == variant_name && matches_at(module_info, rva, pattern)
+                                })
+                            });
+
+            if !cached {
+                patterns.extend(variants.into_iter()
+                                        .map(|(name, pattern)| ((signature.name(), name), pattern)));
+            }
+        }
+
+        Batch {
+            module_info: module_info,
+            key: key,
+            matches: module_info.find_many(&patterns),
+        }
+    }
+
+    fn find_unique<O>(&self,
+                      signature: &'static str,
+                      variant: &'static Variant<O>)
+                      -> Result<LPVOID, FindError> {
+        match self.matches.get(&(signature, variant.name)) {
+            Some(matches) => FindError::unique(matches),
+            // Not part of the batch.
+            None => self.module_info.find_unique(variant.pattern),
+        }
+    }
+}
+
+fn matches_at(module_info: &ModuleInfo, rva: usize, pattern: Pattern) -> bool {
+    rva <= module_info.size && pattern.compare(&module_info.bytes()[rva..])
+}
+
+// The cache is written once the signatures of the batch have been looked up.
+impl<'m> Drop for Batch<'m> {
+    fn drop(&mut self) {
+        cache::flush();
+    }
+}
+
+impl<O> Signature<O> {
+    // Returns the first variant that matches exactly one location in the module, falling back to
+    // the string reference. Pattern matches are remembered in the cache.
+    pub fn find(&self, module_info: &ModuleInfo) -> Result<Found<O>, FindError> {
+        let key = cache::module_key(module_info);
+
+        let found = self.find_with(module_info,
+                                   key.as_ref(),
+                                   |variant| module_info.find_unique(variant.pattern));
+        cache::flush();
+
+        found
+    }
+
+    // Like find(), but takes the pattern matches from the batch.
+    pub fn find_in(&self, batch: &Batch) -> Result<Found<O>, FindError> {
+        self.find_with(batch.module_info, batch.key.as_ref(), |variant| {
+            batch.find_unique(self.name, variant)
+        })
+    }
+
+    fn find_with<F>(&self,
+                    module_info: &ModuleInfo,
+                    key: Option<&cache::ModuleKey>,
+                    find_unique: F)
+                    -> Result<Found<O>, FindError>
+        where F: Fn(&'static Variant<O>) -> Result<LPVOID, FindError>
+    {
+        if let Some(key) = key {
+            if let Some(found) = self.find_cached(module_info, key) {
+                return Ok(found);
+            }
+        }
+
+        let found = self.lookup(|variant| find_unique(variant).map(|address| address as usize),
+                                |string| {
+                                    module_info.find_function_by_string(string)
+                                               .map(|address| address as usize)
+                                });
+
+        found.map(|(address, method)| {
+            if let (Some(key), Method::Pattern(variant)) = (key, method) {
+                let rva = address - module_info.base as usize;
+                cache::store(key, self.name, variant.name, rva);
+            }
+
+            Found {
+                address: address as LPVOID,
+                method: method,
+            }
+        })
+    }
+
+    // Checks the cached address against the pattern of the cached variant, any mismatch drops
+    // the entry.
+    fn find_cached(&self, module_info: &ModuleInfo, key: &cache::ModuleKey) -> Option<Found<O>> {
+        let (rva, variant_name) = match cache::lookup(key, self.name) {
+            Some(entry) => entry,
+            None => return None,
+        };
+
+        let variant = match self.variants.iter().find(|variant| variant.name == variant_name) {
+            Some(variant) if matches_at(module_info, rva, variant.pattern) => variant,
+            _ => {
+                debug!(target: "cache", "Dropping the stale entry for {}", self.name);
+                cache::remove(key, self.name);
+                return None;
+            }
+        };
+
+        Some(Found {
+            address: unsafe { (module_info.base as *mut u8).offset(rva as isize) as LPVOID },
+            method: Method::Cached(variant),
+        })
+    }
+}
+
+impl<O> AddressResult for Result<Found<O>, FindError> {
+    fn to_address_result(&self) -> Result<LPVOID, FindError> {
+        self.map(|found| found.address)
+    }
+
+    fn method(&self) -> Option<String> {
+        self.ok().map(|found| found.method.to_string())
+    }
+}
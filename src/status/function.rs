@@ -0,0 +1,95 @@
+// What happened to each function that a hookable looks for.
+
+use moduleinfo::FindError;
+use std::fmt;
+use winapi::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    NotFound,
+    Ambiguous(usize),
+    // Found and used without hooking, e.g. a function that is only called.
+    Found,
+    Hooked,
+    // Hooked, but the detour is turned off.
+    Disabled,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionStatus {
+    pub name: String,
+    pub address: Option<usize>,
+    // How the address was found, including the matched variant.
+    pub method: Option<String>,
+    pub state: State,
+}
+
+impl FunctionStatus {
+    pub fn new(name: &str,
+               result: Result<LPVOID, FindError>,
+               method: Option<String>)
+               -> FunctionStatus {
+        let (address, state) = match result {
+            Ok(address) => (Some(address as usize), State::Found),
+            Err(FindError::NotFound) => (None, State::NotFound),
+            Err(FindError::Ambiguous(count)) => (None, State::Ambiguous(count)),
+        };
+
+        FunctionStatus {
+            name: name.to_owned(),
+            address: address,
+            method: method,
+            state: state,
+        }
+    }
+}
+
+impl fmt::Display for FunctionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::NotFound => "not found",
+            State::Ambiguous(_) => "ambiguous",
+            State::Found => "found",
+            State::Hooked => "hooked",
+            State::Disabled => "disabled",
+            State::Failed(_) => "failed",
+        };
+
+        let address = self.address.map(|address| format!("{:08X}", address)).unwrap_or_default();
+
+        try!(write!(f, "{:10} {:8}  {}", state, address, self.name));
+
+        if let Some(ref method) = self.method {
+            try!(write!(f, " [{}]", method));
+        }
+
+        match self.state {
+            State::Ambiguous(count) => write!(f, " ({} matches)", count),
+            State::Failed(ref err) => write!(f, ": {}", err),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Updates the functions with what hook! returned for their addresses.
+pub fn record_hooks(functions: &mut [FunctionStatus],
+                    results: Vec<(LPVOID, Result<(), String>)>) {
+    for (address, result) in results {
+        for function in functions.iter_mut()
+                                 .filter(|function| function.address == Some(address as usize)) {
+            function.state = match result {
+                Ok(()) => State::Hooked,
+                Err(ref err) => State::Failed(err.clone()),
+            };
+        }
+    }
+}
+
+// Updates the function at the address after its detour was turned on or off.
+pub fn record_enabled(functions: &mut [FunctionStatus], address: LPVOID, enabled: bool) {
+    for function in functions.iter_mut()
+                             .filter(|function| function.address == Some(address as usize)) {
+        function.state = if enabled { State::Hooked } else { State::Disabled };
+    }
+}
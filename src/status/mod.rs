@@ -0,0 +1,107 @@
+// What was found and hooked in every module, as one block of text that can be pasted into a bug
+// report.
+
+use features;
+use moduleinfo::provider::ModuleProvider;
+use moduleinfo::windows;
+use registry;
+use std::fmt;
+use std::path::PathBuf;
+
+// The statuses of the functions don't depend on Windows so that the offline tools can share them.
+mod function;
+pub use self::function::*;
+
+pub struct ModuleStatus {
+    pub path: Option<PathBuf>,
+    pub base: usize,
+    pub size: usize,
+}
+
+pub struct HookableStatus {
+    pub name: &'static str,
+    pub module: Option<ModuleStatus>,
+    pub functions: Vec<FunctionStatus>,
+    // The features that need the hookable, and whether they're enabled.
+    pub features: Vec<(&'static str, bool)>,
+}
+
+impl fmt::Display for HookableStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.module {
+            Some(ref module) => {
+                let path = module.path
+                                 .as_ref()
+                                 .map(|path| path.to_string_lossy().into_owned())
+                                 .unwrap_or_else(|| "<unknown path>".to_owned());
+
+                try!(writeln!(f,
+                              "{}: {}, base {:08X}, size {:08X}",
+                              self.name,
+                              path,
+                              module.base,
+                              module.size));
+            }
+            None => try!(writeln!(f, "{}: not hooked", self.name)),
+        }
+
+        for function in &self.functions {
+            try!(writeln!(f, "    {}", function));
+        }
+
+        let features = self.features
+                           .iter()
+                           .map(|&(name, enabled)| {
+                               format!("{} {}", if enabled { "✔" } else { "❌" }, name)
+                           })
+                           .collect::<Vec<String>>();
+
+        writeln!(f, "    features: {}", features.join(", "))
+    }
+}
+
+pub struct Report {
+    pub hookables: Vec<HookableStatus>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "Status:"));
+
+        for hookable in &self.hookables {
+            try!(write!(f, "{}", hookable));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn report() -> Report {
+    let hookables = registry::entries()
+        .into_iter()
+        .map(|entry| {
+            let hookable = entry.hookable.read().unwrap();
+
+            HookableStatus {
+                name: entry.name,
+                module: hookable.module_info().map(|module_info| {
+                    ModuleStatus {
+                        path: windows::WINDOWS.get_module_path(module_info.handle),
+                        base: module_info.base as usize,
+                        size: module_info.size,
+                    }
+                }),
+                functions: hookable.functions().to_vec(),
+                features: features::depending_on(entry.name),
+            }
+        })
+        .collect();
+
+    Report { hookables: hookables }
+}
+
+pub fn log() {
+    for line in report().to_string().lines() {
+        info!(target: "status", "{}", line);
+    }
+}
@@ -1,12 +1,16 @@
 use kernel32;
-use moduleinfo::ModuleInfo;
-use psapi;
-use std::{mem, path, ptr};
+use moduleinfo::provider::ModuleProvider;
+use moduleinfo::windows;
+use std::{io, ptr};
+use std::path::PathBuf;
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
 use user32;
 use utils;
-use widestring::WideCStr;
 use winapi::*;
 
+// The handle of this DLL.
+static OWN_MODULE: AtomicUsize = ATOMIC_USIZE_INIT;
+
 pub fn utf16(string: &str) -> Vec<u16> {
     string.encode_utf16().chain(Some(0)).collect()
 }
@@ -20,36 +24,40 @@ pub fn msgbox(message: &str) {
     }
 }
 
-pub fn get_module_info(handle: HMODULE) -> Option<ModuleInfo> {
-    unsafe {
-        let mut info = mem::uninitialized::<MODULEINFO>();
-
-        if psapi::GetModuleInformation(kernel32::GetCurrentProcess(),
-                                       handle,
-                                       &mut info,
-                                       mem::size_of::<MODULEINFO>() as DWORD) != 0 {
-            Some(ModuleInfo {
-                handle: handle,
-                base: info.lpBaseOfDll,
-                size: info.SizeOfImage as usize,
-            })
-        } else {
-            None
-        }
+pub fn set_own_module(module: HMODULE) {
+    OWN_MODULE.store(module as usize, Ordering::SeqCst);
+}
+
+// The directory this DLL was loaded from, for the files it keeps next to itself.
+pub fn own_directory() -> Option<PathBuf> {
+    let module = OWN_MODULE.load(Ordering::SeqCst) as HMODULE;
+    if module.is_null() {
+        return None;
     }
+
+    windows::WINDOWS.get_module_path(module)
+                     .and_then(|path| path.parent().map(|directory| directory.to_path_buf()))
 }
 
-pub fn get_module_path(handle: HMODULE) -> Option<path::PathBuf> {
-    unsafe {
-        let mut filename: [WCHAR; MAX_PATH] = mem::uninitialized();
-
-        let len = kernel32::GetModuleFileNameW(handle, filename.as_mut_ptr(), MAX_PATH as DWORD);
-        if len > 0 {
-            Some(path::PathBuf::from(
-                WideCStr::from_ptr_with_nul(filename.as_ptr(), len as usize).to_os_string()
-            ))
-        } else {
-            None
-        }
+// Overwrites memory regardless of its protection, e.g. code or read-only data.
+pub unsafe fn write_memory(address: usize, bytes: &[u8]) -> io::Result<()> {
+    let mut old_protect = 0;
+    if kernel32::VirtualProtect(address as LPVOID,
+                                bytes.len() as SIZE_T,
+                                PAGE_EXECUTE_READWRITE,
+                                &mut old_protect) == 0 {
+        return Err(io::Error::last_os_error());
     }
+
+    ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+
+    kernel32::VirtualProtect(address as LPVOID,
+                             bytes.len() as SIZE_T,
+                             old_protect,
+                             &mut old_protect);
+    kernel32::FlushInstructionCache(kernel32::GetCurrentProcess(),
+                                    address as LPCVOID,
+                                    bytes.len() as SIZE_T);
+
+    Ok(())
 }
@@ -0,0 +1,221 @@
+// Hooking virtual methods by swapping vtable entries. Interfaces don't need signatures this way,
+// the object is enough.
+
+use function::Function;
+use kernel32;
+use std::{error, fmt, io, mem, ptr, slice};
+use utils;
+use winapi::*;
+
+#[derive(Debug)]
+pub enum Error {
+    // The index is past the copied part of the vtable.
+    OutOfRange(usize),
+    // The vtable doesn't have exactly this many entries.
+    WrongLength(usize),
+    AlreadyHooked(usize),
+    Memory(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::OutOfRange(index) => write!(f, "vtable index {} is out of range", index),
+            Error::WrongLength(len) => write!(f, "the vtable doesn't have {} entries", len),
+            Error::AlreadyHooked(index) => write!(f, "vtable index {} is already hooked", index),
+            Error::Memory(ref err) => write!(f, "memory error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::OutOfRange(_) => "vtable index out of range",
+            Error::WrongLength(_) => "wrong vtable length",
+            Error::AlreadyHooked(_) => "vtable index already hooked",
+            Error::Memory(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Memory(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Unhooks when dropped, so the object has to outlive the hook.
+pub struct VmtHook {
+    object: *mut *const usize,
+    vtable: *const usize,
+    // The copy of the vtable that the object points to instead of the original. It starts with the
+    // RTTI locator that MSVC keeps before the vtable, so dynamic_cast still works.
+    shadow: Option<Box<[usize]>>,
+    // The hooked indices and their original entries.
+    hooked: Vec<(usize, usize)>,
+}
+
+impl Default for VmtHook {
+    fn default() -> Self {
+        VmtHook {
+            object: ptr::null_mut(),
+            vtable: ptr::null(),
+            shadow: None,
+            hooked: Vec::new(),
+        }
+    }
+}
+
+impl VmtHook {
+    // Hooks swap the entries of the vtable itself, so they apply to every object of the class.
+    pub unsafe fn in_place<T>(object: *mut T) -> VmtHook {
+        let object = object as *mut *const usize;
+
+        VmtHook {
+            object: object,
+            vtable: *object,
+            ..VmtHook::default()
+        }
+    }
+
+    // Points the object to a copy of its vtable, so hooks only apply to this object. Calls past
+    // `len` would read past the copy, so the vtable has to end there: the first `len` entries
+    // point to code and the one after doesn't, like the RTTI locator of the next vtable.
+    pub unsafe fn shadow<T>(object: *mut T, len: usize) -> Result<VmtHook, Error> {
+        let object = object as *mut *const usize;
+        let vtable = *object;
+
+        // The copy starts with the RTTI locator before the vtable.
+        let readable = (-1..len as isize + 1).all(|i| is_readable(vtable.offset(i) as usize));
+        let ends_at_len = readable &&
+                          (0..len + 1).all(|i| is_code(*vtable.offset(i as isize)) == (i < len));
+        if !ends_at_len {
+            return Err(Error::WrongLength(len));
+        }
+
+        let shadow = (0..len + 1)
+            .map(|i| *vtable.offset(i as isize - 1))
+            .collect::<Vec<usize>>()
+            .into_boxed_slice();
+        *object = shadow.as_ptr().offset(1);
+
+        Ok(VmtHook {
+            object: object,
+            vtable: vtable,
+            shadow: Some(shadow),
+            hooked: Vec::new(),
+        })
+    }
+
+    pub fn is_hooked(&self, index: usize) -> bool {
+        self.hooked.iter().any(|&(i, _)| i == index)
+    }
+
+    // Replaces the method at the index with the detour. The original method goes into `original`.
+    pub unsafe fn hook<F: Copy>(&mut self,
+                                index: usize,
+                                detour: F,
+                                original: &mut Function<F>)
+                                -> Result<(), Error> {
+        if self.is_hooked(index) {
+            return Err(Error::AlreadyHooked(index));
+        }
+
+        let detour = *(&detour as *const F as *const usize);
+        let entry = try!(self.entry(index));
+
+        let previous = *entry;
+        try!(self.write(entry, detour));
+
+        *(&mut original.ptr as *mut F as *mut usize) = previous;
+        self.hooked.push((index, previous));
+
+        Ok(())
+    }
+
+    // Restores every hooked method and the object's vtable.
+    pub unsafe fn unhook(&mut self) -> Result<(), Error> {
+        if self.object.is_null() {
+            return Ok(());
+        }
+
+        if self.shadow.is_some() {
+            *self.object = self.vtable;
+        } else {
+            while let Some((index, original)) = self.hooked.pop() {
+                let entry = try!(self.entry(index));
+                if let Err(err) = self.write(entry, original) {
+                    self.hooked.push((index, original));
+                    return Err(err);
+                }
+            }
+        }
+
+        // Not `*self = VmtHook::default()`, that would drop and unhook it again.
+        self.object = ptr::null_mut();
+        self.vtable = ptr::null();
+        self.shadow = None;
+        self.hooked.clear();
+
+        Ok(())
+    }
+
+    unsafe fn entry(&mut self, index: usize) -> Result<*mut usize, Error> {
+        match self.shadow {
+            Some(ref mut shadow) => {
+                if index + 1 >= shadow.len() {
+                    return Err(Error::OutOfRange(index));
+                }
+
+                Ok(&mut shadow[index + 1] as *mut usize)
+            }
+            None => Ok(self.vtable.offset(index as isize) as *mut usize),
+        }
+    }
+
+    unsafe fn write(&self, entry: *mut usize, value: usize) -> Result<(), Error> {
+        if self.shadow.is_some() {
+            *entry = value;
+            Ok(())
+        } else {
+            let bytes = slice::from_raw_parts(&value as *const usize as *const u8,
+                                              mem::size_of::<usize>());
+            utils::write_memory(entry as usize, bytes).map_err(Error::Memory)
+        }
+    }
+}
+
+// Whether the address is in committed memory with one of the protections.
+unsafe fn has_protection(address: usize, protections: DWORD) -> bool {
+    let mut info: MEMORY_BASIC_INFORMATION = mem::zeroed();
+
+    if kernel32::VirtualQuery(address as LPCVOID,
+                              &mut info,
+                              mem::size_of::<MEMORY_BASIC_INFORMATION>() as SIZE_T) == 0 {
+        return false;
+    }
+
+    info.State == MEM_COMMIT && info.Protect & protections != 0 && info.Protect & PAGE_GUARD == 0
+}
+
+unsafe fn is_readable(address: usize) -> bool {
+    has_protection(address,
+                   PAGE_READONLY | PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READ |
+                   PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+}
+
+unsafe fn is_code(address: usize) -> bool {
+    has_protection(address,
+                   PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE |
+                   PAGE_EXECUTE_WRITECOPY)
+}
+
+impl Drop for VmtHook {
+    fn drop(&mut self) {
+        if let Err(err) = unsafe { self.unhook() } {
+            error!(target: "vmt", "Error unhooking: {}", err);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+// A length disassembler for 32-bit x86 code. It doesn't decode what the instructions do, only
+// how long they are and where their operands are.
+
+// The position of an operand inside an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operand {
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl Operand {
+    // The value of the operand in the code of the instruction, sign-extended. Operands are little
+    // endian and 1, 2 or 4 bytes long.
+    pub fn read_signed(&self, code: &[u8]) -> isize {
+        let b = &code[self.offset..self.offset + self.size];
+
+        match self.size {
+            1 => b[0] as i8 as isize,
+            2 => (b[0] as u16 | (b[1] as u16) << 8) as i16 as isize,
+            _ => {
+                let value = b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 |
+                            (b[3] as u32) << 24;
+                value as i32 as isize
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub len: usize,
+    // The ModRM memory displacement.
+    pub disp: Option<Operand>,
+    pub imm: Option<Operand>,
+    // The displacement of a relative jump or call, from the end of the instruction.
+    pub rel: Option<Operand>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Imm {
+    None,
+    Byte,
+    Word,
+    // 32 bits, or 16 bits with the operand size prefix.
+    Full,
+    // An address, 32 bits or 16 bits with the address size prefix.
+    Address,
+    // A far pointer, a segment after a full size offset.
+    Far,
+    // ENTER has a 16-bit and an 8-bit immediate.
+    Enter,
+}
+
+struct Opcode {
+    modrm: bool,
+    imm: Imm,
+    rel: bool,
+}
+
+fn op(modrm: bool, imm: Imm, rel: bool) -> Opcode {
+    Opcode {
+        modrm: modrm,
+        imm: imm,
+        rel: rel,
+    }
+}
+
+fn one_byte(opcode: u8, reg: u8) -> Option<Opcode> {
+    let opcode = match opcode {
+        // The arithmetic block: add, or, adc, sbb, and, sub, xor, cmp.
+        0x00...0x3F => match opcode & 7 {
+            0...3 => op(true, Imm::None, false),
+            4 => op(false, Imm::Byte, false),
+            5 => op(false, Imm::Full, false),
+            // push/pop segment, daa, das, aaa, aas. The prefixes never get here.
+            _ => op(false, Imm::None, false),
+        },
+        0x40...0x61 => op(false, Imm::None, false),
+        0x62 | 0x63 => op(true, Imm::None, false),
+        0x68 => op(false, Imm::Full, false),
+        0x69 => op(true, Imm::Full, false),
+        0x6A => op(false, Imm::Byte, false),
+        0x6B => op(true, Imm::Byte, false),
+        0x6C...0x6F => op(false, Imm::None, false),
+        0x70...0x7F => op(false, Imm::Byte, true),
+        0x80 | 0x82 | 0x83 => op(true, Imm::Byte, false),
+        0x81 => op(true, Imm::Full, false),
+        0x84...0x8F => op(true, Imm::None, false),
+        0x90...0x99 => op(false, Imm::None, false),
+        0x9A => op(false, Imm::Far, false),
+        0x9B...0x9F => op(false, Imm::None, false),
+        0xA0...0xA3 => op(false, Imm::Address, false),
+        0xA4...0xA7 => op(false, Imm::None, false),
+        0xA8 => op(false, Imm::Byte, false),
+        0xA9 => op(false, Imm::Full, false),
+        0xAA...0xAF => op(false, Imm::None, false),
+        0xB0...0xB7 => op(false, Imm::Byte, false),
+        0xB8...0xBF => op(false, Imm::Full, false),
+        0xC0 | 0xC1 | 0xC6 => op(true, Imm::Byte, false),
+        0xC2 | 0xCA => op(false, Imm::Word, false),
+        0xC3 | 0xC9 | 0xCB | 0xCC | 0xCE | 0xCF => op(false, Imm::None, false),
+        0xC4 | 0xC5 => op(true, Imm::None, false),
+        0xC7 => op(true, Imm::Full, false),
+        0xC8 => op(false, Imm::Enter, false),
+        0xCD => op(false, Imm::Byte, false),
+        0xD0...0xD3 => op(true, Imm::None, false),
+        0xD4 | 0xD5 => op(false, Imm::Byte, false),
+        0xD6 | 0xD7 => op(false, Imm::None, false),
+        // The FPU instructions.
+        0xD8...0xDF => op(true, Imm::None, false),
+        0xE0...0xE3 | 0xEB => op(false, Imm::Byte, true),
+        0xE4...0xE7 => op(false, Imm::Byte, false),
+        0xE8 | 0xE9 => op(false, Imm::Full, true),
+        0xEA => op(false, Imm::Far, false),
+        0xEC...0xEF | 0xF1 | 0xF4 | 0xF5 => op(false, Imm::None, false),
+        // test has an immediate, the rest of the group doesn't.
+        0xF6 => op(true, if reg < 2 { Imm::Byte } else { Imm::None }, false),
+        0xF7 => op(true, if reg < 2 { Imm::Full } else { Imm::None }, false),
+        0xF8...0xFD => op(false, Imm::None, false),
+        0xFE | 0xFF => op(true, Imm::None, false),
+        _ => return None,
+    };
+
+    Some(opcode)
+}
+
+fn two_byte(opcode: u8) -> Option<Opcode> {
+    let opcode = match opcode {
+        0x00...0x03 | 0x0D => op(true, Imm::None, false),
+        0x05...0x09 | 0x0B | 0x0E => op(false, Imm::None, false),
+        // 3DNow! puts the opcode after the operands.
+        0x0F => op(true, Imm::Byte, false),
+        0x10...0x23 | 0x28...0x2F => op(true, Imm::None, false),
+        0x30...0x37 => op(false, Imm::None, false),
+        0x40...0x6F => op(true, Imm::None, false),
+        0x70...0x73 => op(true, Imm::Byte, false),
+        0x74...0x76 | 0x78...0x7F => op(true, Imm::None, false),
+        0x77 => op(false, Imm::None, false),
+        0x80...0x8F => op(false, Imm::Full, true),
+        0x90...0x9F => op(true, Imm::None, false),
+        0xA0...0xA2 | 0xA8...0xAA => op(false, Imm::None, false),
+        0xA3 | 0xA5 | 0xAB | 0xAD...0xAF => op(true, Imm::None, false),
+        0xA4 | 0xAC => op(true, Imm::Byte, false),
+        0xB0...0xB9 | 0xBB...0xBF => op(true, Imm::None, false),
+        0xBA => op(true, Imm::Byte, false),
+        0xC0 | 0xC1 | 0xC3 | 0xC7 => op(true, Imm::None, false),
+        0xC2 | 0xC4...0xC6 => op(true, Imm::Byte, false),
+        0xC8...0xCF => op(false, Imm::None, false),
+        0xD0...0xFF => op(true, Imm::None, false),
+        _ => return None,
+    };
+
+    Some(opcode)
+}
+
+// Lock, repeat, segment override, operand size and address size.
+const PREFIXES: &'static [u8] = &[0xF0, 0xF2, 0xF3, 0x26, 0x2E, 0x36, 0x3E, 0x64, 0x65, 0x66, 0x67];
+
+pub fn is_prefix(byte: u8) -> bool {
+    PREFIXES.contains(&byte)
+}
+
+// Decodes the instruction at the start of the code. Returns None for invalid or unsupported
+// instructions and for ones that don't fit.
+pub fn decode(code: &[u8]) -> Option<Instruction> {
+    const MAX_LENGTH: usize = 15;
+
+    let mut pos = 0;
+    let mut operand_size_prefix = false;
+    let mut address_size_prefix = false;
+
+    loop {
+        match code.get(pos) {
+            Some(&0x66) => operand_size_prefix = true,
+            Some(&0x67) => address_size_prefix = true,
+            Some(&byte) if is_prefix(byte) => {}
+            Some(_) => break,
+            None => return None,
+        }
+
+        pos += 1;
+    }
+
+    let first = match code.get(pos) {
+        Some(&first) => first,
+        None => return None,
+    };
+    pos += 1;
+
+    // The group opcodes F6 and F7 need the reg field of the ModRM byte to know the immediate.
+    let reg = code.get(pos).map(|&modrm| (modrm >> 3) & 7).unwrap_or(0);
+
+    let opcode = if first != 0x0F {
+        one_byte(first, reg)
+    } else {
+        let second = match code.get(pos) {
+            Some(&second) => second,
+            None => return None,
+        };
+        pos += 1;
+
+        match second {
+            // The three-byte opcodes.
+            0x38 | 0x3A => {
+                if code.get(pos).is_none() {
+                    return None;
+                }
+                pos += 1;
+
+                Some(op(true, if second == 0x3A { Imm::Byte } else { Imm::None }, false))
+            }
+            _ => two_byte(second),
+        }
+    };
+
+    let opcode = match opcode {
+        Some(opcode) => opcode,
+        None => return None,
+    };
+
+    let mut disp = None;
+
+    if opcode.modrm {
+        let modrm = match code.get(pos) {
+            Some(&modrm) => modrm,
+            None => return None,
+        };
+        pos += 1;
+
+        let mode = modrm >> 6;
+        let rm = modrm & 7;
+
+        let disp_size = if address_size_prefix {
+            match (mode, rm) {
+                (0, 6) | (2, _) => 2,
+                (1, _) => 1,
+                _ => 0,
+            }
+        } else {
+            let mut base = rm;
+
+            if mode != 3 && rm == 4 {
+                match code.get(pos) {
+                    Some(&sib) => base = sib & 7,
+                    None => return None,
+                }
+                pos += 1;
+            }
+
+            match (mode, base) {
+                (0, 5) | (2, _) => 4,
+                (1, _) => 1,
+                _ => 0,
+            }
+        };
+
+        if disp_size > 0 {
+            disp = Some(Operand {
+                offset: pos,
+                size: disp_size,
+            });
+            pos += disp_size;
+        }
+    }
+
+    let full = if operand_size_prefix { 2 } else { 4 };
+    let imm_size = match opcode.imm {
+        Imm::None => 0,
+        Imm::Byte => 1,
+        Imm::Word => 2,
+        Imm::Full => full,
+        Imm::Address => if address_size_prefix { 2 } else { 4 },
+        Imm::Far => full + 2,
+        Imm::Enter => 3,
+    };
+
+    let operand = if imm_size > 0 {
+        Some(Operand {
+            offset: pos,
+            size: imm_size,
+        })
+    } else {
+        None
+    };
+    pos += imm_size;
+
+    if pos > code.len() || pos > MAX_LENGTH {
+        return None;
+    }
+
+    let (imm, rel) = if opcode.rel {
+        (None, operand)
+    } else {
+        (operand, None)
+    };
+
+    Some(Instruction {
+        len: pos,
+        disp: disp,
+        imm: imm,
+        rel: rel,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset: usize, size: usize) -> Option<Operand> {
+        Some(Operand {
+            offset: offset,
+            size: size,
+        })
+    }
+
+    fn len(code: &[u8]) -> usize {
+        decode(code).unwrap().len
+    }
+
+    #[test]
+    fn decode_one_byte() {
+        // push ebp; mov ebp, esp; ret; int3
+        assert_eq!(len(&[0x55]), 1);
+        assert_eq!(len(&[0x8B, 0xEC]), 2);
+        assert_eq!(len(&[0xC3]), 1);
+        assert_eq!(len(&[0xCC]), 1);
+        // ret 8
+        assert_eq!(len(&[0xC2, 0x08, 0x00]), 3);
+    }
+
+    #[test]
+    fn decode_rel32() {
+        // call rel32
+        assert_eq!(decode(&[0xE8, 0x10, 0x20, 0x30, 0x40]),
+                   Some(Instruction {
+                       len: 5,
+                       disp: None,
+                       imm: None,
+                       rel: at(1, 4),
+                   }));
+        // jmp rel32
+        assert_eq!(decode(&[0xE9, 0x00, 0x00, 0x00, 0x00]).unwrap().rel, at(1, 4));
+        // jz rel32
+        assert_eq!(decode(&[0x0F, 0x84, 0x00, 0x00, 0x00, 0x00]),
+                   Some(Instruction {
+                       len: 6,
+                       disp: None,
+                       imm: None,
+                       rel: at(2, 4),
+                   }));
+    }
+
+    #[test]
+    fn decode_rel8() {
+        // jmp short, jnz short, loop, jecxz
+        for &opcode in &[0xEB, 0x75, 0xE2, 0xE3] {
+            assert_eq!(decode(&[opcode, 0xFE]).unwrap().rel, at(1, 1));
+        }
+    }
+
+    #[test]
+    fn decode_modrm() {
+        // mov eax, [ebp+8]
+        assert_eq!(decode(&[0x8B, 0x45, 0x08]),
+                   Some(Instruction {
+                       len: 3,
+                       disp: at(2, 1),
+                       imm: None,
+                       rel: None,
+                   }));
+        // mov eax, [ebp+0x100]
+        assert_eq!(decode(&[0x8B, 0x85, 0x00, 0x01, 0x00, 0x00]).unwrap().disp, at(2, 4));
+        // mov eax, [0x12345678]
+        assert_eq!(decode(&[0x8B, 0x05, 0x78, 0x56, 0x34, 0x12]).unwrap().disp, at(2, 4));
+        // mov eax, [ebp] has a zero disp8, there's no mode 0 encoding for it.
+        assert_eq!(decode(&[0x8B, 0x45, 0x00]).unwrap().disp, at(2, 1));
+    }
+
+    #[test]
+    fn decode_sib() {
+        // mov eax, [esp+4]
+        assert_eq!(decode(&[0x8B, 0x44, 0x24, 0x04]).unwrap().disp, at(3, 1));
+        // mov eax, [esp]
+        assert_eq!(decode(&[0x8B, 0x04, 0x24]),
+                   Some(Instruction {
+                       len: 3,
+                       disp: None,
+                       imm: None,
+                       rel: None,
+                   }));
+        // mov eax, [ecx*4+0x12345678], SIB with no base.
+        assert_eq!(decode(&[0x8B, 0x04, 0x8D, 0x78, 0x56, 0x34, 0x12]),
+                   Some(Instruction {
+                       len: 7,
+                       disp: at(3, 4),
+                       imm: None,
+                       rel: None,
+                   }));
+        // mov eax, [esi+ecx*4+0x100]
+        assert_eq!(decode(&[0x8B, 0x84, 0x8E, 0x00, 0x01, 0x00, 0x00]).unwrap().disp,
+                   at(3, 4));
+    }
+
+    #[test]
+    fn decode_imm32() {
+        // mov eax, imm32; mov edi, imm32
+        assert_eq!(decode(&[0xB8, 0x78, 0x56, 0x34, 0x12]),
+                   Some(Instruction {
+                       len: 5,
+                       disp: None,
+                       imm: at(1, 4),
+                       rel: None,
+                   }));
+        assert_eq!(decode(&[0xBF, 0x00, 0x00, 0x00, 0x00]).unwrap().imm, at(1, 4));
+        // mov ax, imm16
+        assert_eq!(decode(&[0x66, 0xB8, 0x34, 0x12]).unwrap().imm, at(2, 2));
+        // mov dword ptr [ebp-4], imm32
+        assert_eq!(decode(&[0xC7, 0x45, 0xFC, 0x01, 0x00, 0x00, 0x00]),
+                   Some(Instruction {
+                       len: 7,
+                       disp: at(2, 1),
+                       imm: at(3, 4),
+                       rel: None,
+                   }));
+        // mov dword ptr [0x12345678], imm32
+        assert_eq!(decode(&[0xC7, 0x05, 0x78, 0x56, 0x34, 0x12, 0x01, 0x00, 0x00, 0x00]),
+                   Some(Instruction {
+                       len: 10,
+                       disp: at(2, 4),
+                       imm: at(6, 4),
+                       rel: None,
+                   }));
+        // push imm32; sub esp, imm32; mov eax, [0x12345678]
+        assert_eq!(decode(&[0x68, 0x00, 0x00, 0x00, 0x00]).unwrap().imm, at(1, 4));
+        assert_eq!(decode(&[0x81, 0xEC, 0x00, 0x01, 0x00, 0x00]).unwrap().imm, at(2, 4));
+        assert_eq!(decode(&[0xA1, 0x78, 0x56, 0x34, 0x12]).unwrap().imm, at(1, 4));
+    }
+
+    #[test]
+    fn decode_group_immediates() {
+        // test byte ptr [eax], 1 has an immediate, not byte ptr [eax] has none.
+        assert_eq!(len(&[0xF6, 0x00, 0x01]), 3);
+        assert_eq!(len(&[0xF6, 0x10]), 2);
+        // test eax, imm32 and neg eax.
+        assert_eq!(len(&[0xF7, 0xC0, 0x01, 0x00, 0x00, 0x00]), 6);
+        assert_eq!(len(&[0xF7, 0xD8]), 2);
+    }
+
+    #[test]
+    fn decode_prefixes() {
+        // mov eax, fs:[0]
+        assert_eq!(decode(&[0x64, 0xA1, 0x00, 0x00, 0x00, 0x00]).unwrap().imm, at(2, 4));
+        // rep movsd
+        assert_eq!(len(&[0xF3, 0xA5]), 2);
+        // movss xmm0, [esp+4]
+        assert_eq!(decode(&[0xF3, 0x0F, 0x10, 0x44, 0x24, 0x04]).unwrap().disp, at(5, 1));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&[0xE8, 0x00, 0x00, 0x00]), None);
+        assert_eq!(decode(&[0x8B]), None);
+        assert_eq!(decode(&[0x8B, 0x44]), None);
+        assert_eq!(decode(&[0xC7, 0x05, 0x78, 0x56, 0x34, 0x12, 0x01]), None);
+        assert_eq!(decode(&[0x66]), None);
+        assert_eq!(decode(&[0x0F]), None);
+    }
+}
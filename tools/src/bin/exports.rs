@@ -0,0 +1,131 @@
+// Lists the exports of a DLL on disk, or looks one of them up.
+//
+// Usage: exports <dll> [name or #ordinal]
+//
+// Forwarded exports are followed into the DLLs next to the given one.
+
+// try! and slicing instead of str::strip_prefix like the shared files, see the lint baseline in
+// lib.rs.
+#![allow(clippy::manual_strip)]
+#![allow(deprecated)]
+
+extern crate hlsautopause_tools as tools;
+
+use std::{env, fs, process};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tools::moduleinfo::pe::{self, Export, ExportRef, ExportTarget, Layout, Pe};
+
+// Forwarders can chain, but never this deep unless they loop.
+const MAX_FORWARDER_DEPTH: usize = 8;
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    try!(try!(fs::File::open(path)).read_to_end(&mut data));
+    Ok(data)
+}
+
+fn with_pe<F, T>(path: &Path, f: F) -> Result<T, String>
+    where F: FnOnce(&Pe) -> T
+{
+    let file = try!(read_file(path).map_err(|e| format!("error reading the file: {}", e)));
+    let pe = try!(Pe::parse(&file, Layout::File)
+        .map_err(|e| format!("error parsing the file: {}", e)));
+
+    Ok(f(&pe))
+}
+
+fn parse_export<'a>(string: &'a str) -> Option<ExportRef<'a>> {
+    if string.starts_with('#') {
+        string[1..].parse().ok().map(ExportRef::Ordinal)
+    } else {
+        Some(ExportRef::Name(string))
+    }
+}
+
+// Finds the module a forwarder points to in the same directory, ignoring the case like Windows.
+fn find_sibling(path: &Path, module: &str) -> Option<PathBuf> {
+    let filename = format!("{}.dll", module).to_lowercase();
+    let directory = match path.parent() {
+        Some(directory) if directory != Path::new("") => directory,
+        _ => Path::new("."),
+    };
+
+    fs::read_dir(directory)
+        .ok()
+        .and_then(|entries| {
+            entries.filter_map(|entry| entry.ok())
+                   .map(|entry| entry.path())
+                   .find(|path| {
+                       path.file_name()
+                           .map(|name| name.to_string_lossy().to_lowercase())
+                           .as_ref() == Some(&filename)
+                   })
+        })
+}
+
+fn resolve(path: &Path, export: ExportRef, depth: usize) -> Result<(), String> {
+    let target = try!(with_pe(path, |pe| pe.export(export)));
+
+    match target {
+        Some(ExportTarget::Rva(rva)) => {
+            println!("{}: {} at {:08X}", path.display(), export, rva);
+            Ok(())
+        }
+        Some(ExportTarget::Forwarder(forwarder)) => {
+            println!("{}: {} is forwarded to {}", path.display(), export, forwarder);
+
+            let (module, forwarded) = try!(pe::parse_forwarder(&forwarder)
+                .ok_or_else(|| format!("invalid forwarder {}", forwarder)));
+
+            if depth == 0 {
+                return Err("too many forwarders".to_owned());
+            }
+
+            let sibling = try!(find_sibling(path, module)
+                .ok_or_else(|| format!("can't find {}.dll next to the file", module)));
+
+            resolve(&sibling, forwarded, depth - 1)
+        }
+        None => Err(format!("{} is not exported", export)),
+    }
+}
+
+fn print_export(export: &Export) {
+    let name = export.name.as_ref().map(|name| &name[..]).unwrap_or("<no name>");
+
+    match export.target {
+        ExportTarget::Rva(rva) => println!("{:5}  {:08X}  {}", export.ordinal, rva, name),
+        ExportTarget::Forwarder(ref forwarder) => {
+            println!("{:5}  {:8}  {} -> {}", export.ordinal, "", name, forwarder)
+        }
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    if args.is_empty() || args.len() > 2 {
+        let _ = writeln!(io::stderr(), "Usage: exports <dll> [name or #ordinal]");
+        process::exit(2);
+    }
+
+    let path = Path::new(&args[0]);
+
+    let result = match args.get(1) {
+        Some(export) => {
+            match parse_export(export) {
+                Some(export) => resolve(path, export, MAX_FORWARDER_DEPTH),
+                None => Err(format!("invalid ordinal \"{}\"", export)),
+            }
+        }
+        None => with_pe(path, |pe| for export in pe.exports() {
+            print_export(&export);
+        }),
+    };
+
+    if let Err(err) = result {
+        let _ = writeln!(io::stderr(), "{}: {}", path.display(), err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,76 @@
+// Lists the classes with RTTI in a DLL on disk, or shows the vtables and bases of one of them.
+//
+// Usage: rtti <dll> [class]
+//
+// The class can be given by its plain name, like `CHL1GameMovement`, or its mangled name.
+
+// try! like the shared files, see the lint baseline in lib.rs.
+#![allow(deprecated)]
+
+extern crate hlsautopause_tools as tools;
+
+use std::{env, fs, process};
+use std::io::{self, Read, Write};
+use tools::moduleinfo::pe::{self, Layout, Pe};
+use tools::moduleinfo::rtti;
+
+fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    try!(try!(fs::File::open(path)).read_to_end(&mut data));
+    Ok(data)
+}
+
+fn show_class(pe: &Pe, class: &str) -> Result<(), String> {
+    let base = pe.image_base as usize;
+    let vtables = rtti::find_vtables(pe, base, class);
+
+    if vtables.is_empty() {
+        return Err(format!("no vtables found for {}", class));
+    }
+
+    for vtable in &vtables {
+        println!("vtable {:08X}, offset {}", vtable.rva, vtable.offset);
+    }
+
+    if let Some(vtable) = vtables.iter().find(|vtable| vtable.offset == 0) {
+        println!("bases:");
+
+        for descriptor in rtti::base_classes(pe, base, vtable.locator) {
+            println!("    {}", descriptor.demangled_name());
+        }
+    }
+
+    Ok(())
+}
+
+fn run(path: &str, class: Option<&str>) -> Result<(), String> {
+    let file = try!(read_file(path).map_err(|e| format!("error reading the file: {}", e)));
+    let image = try!(pe::map_image(&file).map_err(|e| format!("error mapping the file: {}", e)));
+    let pe = try!(Pe::parse(&image, Layout::Image)
+        .map_err(|e| format!("error parsing the mapped image: {}", e)));
+
+    match class {
+        Some(class) => show_class(&pe, class),
+        None => {
+            for descriptor in rtti::type_descriptors(&pe) {
+                println!("{:08X}  {}", descriptor.rva, descriptor.demangled_name());
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    if args.is_empty() || args.len() > 2 {
+        let _ = writeln!(io::stderr(), "Usage: rtti <dll> [class]");
+        process::exit(2);
+    }
+
+    if let Err(err) = run(&args[0], args.get(1).map(|class| &class[..])) {
+        let _ = writeln!(io::stderr(), "{}: {}", args[0], err);
+        process::exit(1);
+    }
+}
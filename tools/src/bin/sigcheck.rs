@@ -0,0 +1,128 @@
+// Checks the engine, server and client signatures against DLLs on disk, without launching the
+// game.
+//
+// Usage: sigcheck <path>...
+//
+// Every path is either a DLL or a directory that is searched recursively for engine.dll,
+// server.dll and client.dll. The exit code is non-zero if any signature is missing or ambiguous.
+
+// try! and bare trait objects like the shared files, see the lint baseline in lib.rs.
+#![allow(bare_trait_objects)]
+#![allow(deprecated)]
+
+extern crate hlsautopause_tools as tools;
+
+use std::{env, fs, process};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tools::moduleinfo::FindError;
+use tools::moduleinfo::pe::{self, Layout, Pe};
+use tools::signature::AnySignature;
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries = try!(try!(fs::read_dir(path)).collect::<io::Result<Vec<fs::DirEntry>>>());
+    entries.sort_by_key(|entry| entry.path());
+
+    for entry in entries {
+        let path = entry.path();
+
+        if path.is_dir() {
+            try!(collect_files(&path, files));
+        } else if filename(&path).and_then(|name| tools::signatures_for(&name)).is_some() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn filename(path: &Path) -> Option<String> {
+    path.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    try!(try!(fs::File::open(path)).read_to_end(&mut data));
+    Ok(data)
+}
+
+// Returns whether every signature was found exactly once.
+fn check(path: &Path, signatures: &[&AnySignature]) -> Result<bool, String> {
+    let file = try!(read_file(path).map_err(|e| format!("error reading the file: {}", e)));
+    let image = try!(pe::map_image(&file).map_err(|e| format!("error mapping the file: {}", e)));
+    let pe = try!(Pe::parse(&image, Layout::Image)
+        .map_err(|e| format!("error parsing the mapped image: {}", e)));
+
+    println!("{}: timestamp {:08X}, image size {:08X}",
+             path.display(),
+             pe.timestamp,
+             pe.size_of_image);
+
+    let mut all_found = true;
+
+    for signature in signatures {
+        // Looked up exactly like the DLL does it, minus the cache.
+        match signature.find_in_image(&image) {
+            Ok((rva, method)) => {
+                println!("  found      {:08X}  {} [{}]", rva, signature.name(), method)
+            }
+            Err(FindError::NotFound) => {
+                all_found = false;
+                println!("  missing              {}", signature.name());
+            }
+            Err(FindError::Ambiguous(count)) => {
+                all_found = false;
+                println!("  ambiguous  {:8}  {} ({} matches)", "", signature.name(), count);
+            }
+        }
+    }
+
+    Ok(all_found)
+}
+
+fn main() {
+    let paths = env::args_os().skip(1).map(PathBuf::from).collect::<Vec<PathBuf>>();
+
+    if paths.is_empty() {
+        let _ = writeln!(io::stderr(),
+                         "Usage: sigcheck <engine.dll, server.dll, client.dll or directory>...");
+        process::exit(2);
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        if let Err(err) = collect_files(path, &mut files) {
+            let _ = writeln!(io::stderr(), "{}: {}", path.display(), err);
+            process::exit(2);
+        }
+    }
+
+    let mut success = true;
+
+    for path in files {
+        let signatures = match filename(&path).and_then(|name| tools::signatures_for(&name)) {
+            Some(signatures) => signatures,
+            None => {
+                let _ = writeln!(io::stderr(),
+                                 "{}: no signatures for this module, skipping",
+                                 path.display());
+                continue;
+            }
+        };
+
+        match check(&path, signatures) {
+            Ok(all_found) => success &= all_found,
+            Err(err) => {
+                success = false;
+                let _ = writeln!(io::stderr(), "{}: {}", path.display(), err);
+            }
+        }
+    }
+
+    process::exit(if success { 0 } else { 1 });
+}
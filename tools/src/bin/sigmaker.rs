@@ -0,0 +1,88 @@
+// Generates a pattern for a function in a DLL on disk.
+//
+// Usage: sigmaker <dll> <address> [name]
+//
+// The address is a hexadecimal RVA, or a virtual address if it's past the image base. The output
+// can be pasted into the source as is.
+
+// try! like the shared files, see the lint baseline in lib.rs.
+#![allow(deprecated)]
+
+extern crate hlsautopause_tools as tools;
+
+use std::{env, fs, process};
+use std::io::{self, Read, Write};
+use tools::moduleinfo::pe::{self, Layout, Pe};
+
+// Wrap the bytes like the patterns in the source.
+const BYTES_PER_LINE: usize = 32;
+
+fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    try!(try!(fs::File::open(path)).read_to_end(&mut data));
+    Ok(data)
+}
+
+fn parse_address(string: &str) -> Option<u64> {
+    let digits = if string.starts_with("0x") || string.starts_with("0X") {
+        &string[2..]
+    } else {
+        string
+    };
+
+    u64::from_str_radix(digits, 16).ok()
+}
+
+fn run(path: &str, address: &str, name: &str) -> Result<(), String> {
+    let address = try!(parse_address(address)
+        .ok_or_else(|| format!("invalid address \"{}\"", address)));
+
+    let file = try!(read_file(path).map_err(|e| format!("error reading the file: {}", e)));
+    let image = try!(pe::map_image(&file).map_err(|e| format!("error mapping the file: {}", e)));
+    let pe = try!(Pe::parse(&image, Layout::Image)
+        .map_err(|e| format!("error parsing the mapped image: {}", e)));
+
+    let rva = if address >= pe.image_base {
+        address - pe.image_base
+    } else {
+        address
+    } as usize;
+
+    let pattern = try!(tools::sigmaker::make_pattern(&pe, rva)
+        .map_err(|e| format!("error making a pattern for {:08X}: {}", rva, e)));
+
+    let _ = writeln!(io::stderr(),
+                     "{:08X}: {} bytes, {} wildcards",
+                     rva,
+                     pattern.bytes.len(),
+                     pattern.bytes.iter().filter(|&&(_, m)| m == 0).count());
+
+    println!("pattern!({}", name);
+    for chunk in pattern.bytes.chunks(BYTES_PER_LINE) {
+        let line = tools::pattern::Pattern {
+            bytes: chunk,
+            captures: &[],
+        };
+
+        println!("    {}", tools::sigmaker::to_macro(line));
+    }
+    println!(");");
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    if args.len() < 2 || args.len() > 3 {
+        let _ = writeln!(io::stderr(), "Usage: sigmaker <dll> <address> [name]");
+        process::exit(2);
+    }
+
+    let name = args.get(2).map(|name| &name[..]).unwrap_or("NAME");
+
+    if let Err(err) = run(&args[0], &args[1], name) {
+        let _ = writeln!(io::stderr(), "{}: {}", args[0], err);
+        process::exit(1);
+    }
+}
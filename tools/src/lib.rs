@@ -0,0 +1,168 @@
+// Offline tools that work on PE files on disk, so they run on any platform. They share the
+// Windows-independent modules with the DLL.
+
+// The shared files are written like the rest of the DLL, for the nightly it's built with: try!,
+// `...` ranges, bare trait objects, explicit field names and 'static, match instead of `?`, and no
+// str::strip_prefix, div_ceil, RangeInclusive::contains or Option::is_some_and. Each one allows
+// only the lints that ask for newer syntax or helpers, everything else has to pass clippy -D
+// warnings.
+
+extern crate libc;
+#[macro_use]
+extern crate log;
+
+// The Windows types that the shared files use. Only pointers to them are passed around, so they
+// don't need the real definitions.
+pub mod winapi {
+    pub use libc::c_void;
+
+    pub enum HINSTANCE__ {}
+    pub type HMODULE = *mut HINSTANCE__;
+    pub type LPVOID = *mut c_void;
+}
+
+// Only pattern! is used here, the rest needs Windows.
+#[allow(unused_macros)]
+#[macro_use]
+#[path = "../../src/macros.rs"]
+mod macros;
+
+#[allow(bare_trait_objects, clippy::redundant_static_lifetimes, clippy::unnecessary_map_or)]
+#[path = "../../src/matcher.rs"]
+pub mod matcher;
+
+#[path = "../../src/hookable.rs"]
+pub mod hookable;
+
+#[allow(deprecated,
+        clippy::manual_is_multiple_of,
+        clippy::manual_range_patterns,
+        clippy::manual_strip,
+        clippy::redundant_field_names,
+        clippy::redundant_static_lifetimes)]
+#[path = "../../src/pattern.rs"]
+pub mod pattern;
+
+#[path = "../../src/signature/definition.rs"]
+pub mod signature;
+
+#[allow(bare_trait_objects,
+        deprecated,
+        clippy::match_like_matches_macro,
+        clippy::unnecessary_map_or)]
+#[path = "../../src/iat/entries.rs"]
+pub mod iat;
+
+#[allow(clippy::question_mark, clippy::unnecessary_map_or)]
+#[path = "../../src/interfaces/list_head.rs"]
+pub mod interfaces;
+
+#[allow(bare_trait_objects,
+        clippy::missing_const_for_thread_local,
+        clippy::redundant_field_names,
+        clippy::redundant_static_lifetimes,
+        clippy::unnecessary_map_or)]
+#[path = "../../src/registry/hooking.rs"]
+pub mod registry;
+
+#[allow(deprecated, clippy::redundant_field_names)]
+#[path = "../../src/status/function.rs"]
+pub mod status;
+
+// Paths can't go through the directories of inline modules since they don't exist, so the shared
+// files are declared here and re-exported under the DLL's module paths.
+#[allow(bare_trait_objects, deprecated)]
+#[path = "../../src/moduleinfo/module.rs"]
+pub mod module;
+
+#[allow(deprecated,
+        clippy::manual_div_ceil,
+        clippy::manual_strip,
+        clippy::question_mark,
+        clippy::redundant_field_names)]
+#[path = "../../src/moduleinfo/pe.rs"]
+pub mod pe;
+
+#[allow(clippy::redundant_field_names, clippy::unnecessary_map_or)]
+#[path = "../../src/moduleinfo/provider.rs"]
+pub mod provider;
+
+#[allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+#[path = "../../src/moduleinfo/rtti.rs"]
+pub mod rtti;
+
+#[path = "../../src/moduleinfo/scan.rs"]
+pub mod scan;
+
+#[allow(deprecated, clippy::question_mark, clippy::redundant_field_names)]
+#[path = "../../src/moduleinfo/version.rs"]
+pub mod version;
+
+#[allow(clippy::manual_is_multiple_of,
+        clippy::manual_range_contains,
+        clippy::redundant_static_lifetimes)]
+#[path = "../../src/moduleinfo/xref.rs"]
+pub mod xref;
+
+#[allow(bare_trait_objects, non_upper_case_globals, clippy::redundant_static_lifetimes)]
+#[path = "../../src/hooks/client/signatures.rs"]
+pub mod client_signatures;
+
+#[allow(bare_trait_objects, non_upper_case_globals, clippy::redundant_static_lifetimes)]
+#[path = "../../src/hooks/engine/signatures.rs"]
+pub mod engine_signatures;
+
+#[allow(bare_trait_objects, non_upper_case_globals, clippy::redundant_static_lifetimes)]
+#[path = "../../src/hooks/server/signatures.rs"]
+pub mod server_signatures;
+
+pub mod sigmaker;
+
+#[allow(ellipsis_inclusive_range_patterns,
+        clippy::question_mark,
+        clippy::redundant_field_names,
+        clippy::redundant_static_lifetimes)]
+#[path = "../../src/x86.rs"]
+pub mod x86;
+
+#[allow(deprecated, ellipsis_inclusive_range_patterns, clippy::redundant_field_names)]
+#[path = "../../src/detour/trampoline.rs"]
+pub mod trampoline;
+
+pub mod moduleinfo {
+    pub use module::ModuleInfo;
+    pub use pattern::FindError;
+    pub use pe;
+    pub use provider;
+    pub use rtti;
+    pub use scan;
+    pub use scan::Scope;
+    pub use version;
+    pub use xref;
+}
+
+pub mod hooks {
+    pub mod client {
+        pub use client_signatures as signatures;
+    }
+
+    pub mod engine {
+        pub use engine_signatures as signatures;
+    }
+
+    pub mod server {
+        pub use server_signatures as signatures;
+    }
+}
+
+use signature::AnySignature;
+
+// The signatures that apply to a module, by its file name.
+pub fn signatures_for(filename: &str) -> Option<&'static [&'static dyn AnySignature]> {
+    match &filename.to_lowercase()[..] {
+        "client.dll" => Some(hooks::client::signatures::SIGNATURES),
+        "engine.dll" => Some(hooks::engine::signatures::SIGNATURES),
+        "server.dll" => Some(hooks::server::signatures::SIGNATURES),
+        _ => None,
+    }
+}
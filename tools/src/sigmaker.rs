@@ -0,0 +1,243 @@
+// Generates the shortest pattern that finds a function, wildcarding everything that changes when
+// the module is rebased or the code around the function is moved.
+
+use moduleinfo::scan::{self, Scope};
+use pattern::{OwnedPattern, Pattern};
+use pe::Pe;
+use std::{error, fmt};
+use std::collections::HashSet;
+use x86;
+
+// Patterns longer than this are unlikely to survive an update anyway.
+const MAX_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotExecutable,
+    Undecodable(usize),
+    NotUnique(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotExecutable => write!(f, "the address is not in an executable section"),
+            Error::Undecodable(rva) => write!(f, "can't decode the instruction at {:08X}", rva),
+            Error::NotUnique(len) => write!(f, "not unique even with {} bytes", len),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NotExecutable => "the address is not in an executable section",
+            Error::Undecodable(_) => "can't decode the instruction",
+            Error::NotUnique(_) => "not unique",
+        }
+    }
+}
+
+// The shortest prefix of the code at the RVA that matches only there. Bytes patched by base
+// relocations and rel32 jump and call targets are wildcards.
+pub fn make_pattern(pe: &Pe, rva: usize) -> Result<OwnedPattern, Error> {
+    let section = match pe.section_for_rva(rva) {
+        Some(section) if section.is_executable() => section,
+        _ => return Err(Error::NotExecutable),
+    };
+
+    let end = section.rva_range().end;
+    let code = match pe.read(rva, ::std::cmp::min(end - rva, MAX_LENGTH)) {
+        Some(code) => code,
+        None => return Err(Error::NotExecutable),
+    };
+
+    let relocated = pe.relocations()
+                      .into_iter()
+                      .flat_map(|relocation| relocation.rva..relocation.rva + relocation.size)
+                      .collect::<HashSet<usize>>();
+
+    let mut bytes = Vec::new();
+
+    while bytes.len() < code.len() {
+        let offset = bytes.len();
+
+        let instruction = match x86::decode(&code[offset..]) {
+            Some(instruction) => instruction,
+            // Usable if the part so far is already unique.
+            None if offset > 0 => break,
+            None => return Err(Error::Undecodable(rva)),
+        };
+
+        for i in 0..instruction.len {
+            let wildcard = relocated.contains(&(rva + offset + i)) ||
+                           match instruction.rel {
+                Some(rel) => rel.size > 1 && i >= rel.offset && i < rel.offset + rel.size,
+                None => false,
+            };
+
+            bytes.push(if wildcard {
+                (0x00, 0x00)
+            } else {
+                (code[offset + i], 0xFF)
+            });
+        }
+    }
+
+    let count = |len: usize| {
+        let pattern = Pattern {
+            bytes: &bytes[..len],
+            captures: &[],
+        };
+
+        scan::find_all_in(pe.data(), Scope::Executable, pattern).len()
+    };
+
+    if bytes.is_empty() || count(bytes.len()) != 1 {
+        return Err(Error::NotUnique(bytes.len()));
+    }
+
+    // Lengthening a pattern can only remove matches, so the shortest unique length can be found
+    // with a binary search.
+    let mut low = 1;
+    let mut high = bytes.len();
+
+    while low < high {
+        let middle = (low + high) / 2;
+
+        if count(middle) == 1 {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    bytes.truncate(high);
+
+    Ok(OwnedPattern {
+        bytes,
+        captures: Vec::new(),
+    })
+}
+
+// Formats the pattern the way the pattern! macro takes it, e.g. `0x8B 0x51 ?? (0x80 & 0xF0)`.
+pub fn to_macro(pattern: Pattern) -> String {
+    let mut rv = String::new();
+
+    for (i, &(b, m)) in pattern.bytes.iter().enumerate() {
+        if i > 0 {
+            rv.push(' ');
+        }
+
+        for capture in pattern.captures.iter().filter(|capture| capture.offset == i) {
+            rv.push_str(&format!("@{} ", capture.name));
+        }
+
+        match m {
+            0xFF => rv.push_str(&format!("0x{:02X}", b)),
+            0x00 => rv.push_str("??"),
+            _ => rv.push_str(&format!("(0x{:02X} & 0x{:02X})", b, m)),
+        }
+    }
+
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pattern::Capture;
+    use pe::{IMAGE_DIRECTORY_ENTRY_BASERELOC, Layout};
+    use pe::builder::*;
+
+    // push ebp; mov ebp, esp; mov eax, [abs32]; call rel32; then the rest.
+    fn function(code: &mut Vec<u8>, at: usize, abs: u32, rel: u32, rest: &[u8]) {
+        code.resize(at, 0xCC);
+        put(code, at, &[0x55, 0x8B, 0xEC, 0xA1]);
+        put_u32(code, at + 4, abs);
+        put(code, at + 8, &[0xE8]);
+        put_u32(code, at + 9, rel);
+        put(code, at + 13, rest);
+    }
+
+    // The functions, with a .reloc block that covers the abs32 operand of each.
+    fn image_with(functions: &[usize], code: &[u8]) -> Vec<u8> {
+        let mut reloc = Vec::new();
+        put_u32(&mut reloc, 0, 0x1000);
+        put_u32(&mut reloc, 4, 8 + 2 * functions.len() as u32);
+        for (i, &at) in functions.iter().enumerate() {
+            put_u16(&mut reloc, 8 + i * 2, 0x3000 | (at as u16 + 4));
+        }
+
+        let mut image = build_image(&[code, &reloc]);
+        set_directory(&mut image, IMAGE_DIRECTORY_ENTRY_BASERELOC, 0x2000, reloc.len() as u32);
+        image
+    }
+
+    #[test]
+    fn make_pattern_wildcards_and_shortest() {
+        // The second function only differs from the first after the call, once the relocated
+        // address and the call target are ignored.
+        let mut code = Vec::new();
+        function(&mut code, 0x00, 0x1000_3000, 0x100, &[0x5D, 0xC3]);
+        function(&mut code, 0x40, 0x1000_3010, 0x200, &[0x33, 0xC0, 0x5D, 0xC3]);
+        let image = image_with(&[0x00, 0x40], &code);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        let pattern = make_pattern(&pe, 0x1000).unwrap();
+        assert_eq!(to_macro(pattern.as_pattern()),
+                   "0x55 0x8B 0xEC 0xA1 ?? ?? ?? ?? 0xE8 ?? ?? ?? ?? 0x5D");
+
+        let pattern = make_pattern(&pe, 0x1040).unwrap();
+        assert_eq!(to_macro(pattern.as_pattern()),
+                   "0x55 0x8B 0xEC 0xA1 ?? ?? ?? ?? 0xE8 ?? ?? ?? ?? 0x33");
+    }
+
+    #[test]
+    fn make_pattern_without_relocations() {
+        // Without the .reloc block the addresses are part of the pattern, and they already tell
+        // the functions apart.
+        let mut code = Vec::new();
+        function(&mut code, 0x00, 0x1000_3000, 0x100, &[0x5D, 0xC3]);
+        function(&mut code, 0x40, 0x1000_3010, 0x200, &[0x33, 0xC0, 0x5D, 0xC3]);
+        let image = build_image(&[&code]);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        let pattern = make_pattern(&pe, 0x1000).unwrap();
+        assert_eq!(to_macro(pattern.as_pattern()), "0x55 0x8B 0xEC 0xA1 0x00");
+    }
+
+    #[test]
+    fn make_pattern_errors() {
+        // The same function twice, up to an instruction that can't be decoded.
+        let mut code = Vec::new();
+        function(&mut code, 0x00, 0x1000_3000, 0x100, &[0x5D, 0xC3, 0x0F, 0x0A]);
+        function(&mut code, 0x40, 0x1000_3010, 0x200, &[0x5D, 0xC3, 0x0F, 0x0A]);
+        let image = image_with(&[0x00, 0x40], &code);
+        let pe = Pe::parse(&image, Layout::Image).unwrap();
+
+        assert_eq!(make_pattern(&pe, 0x1040).err(), Some(Error::NotUnique(15)));
+        assert_eq!(make_pattern(&pe, 0x104F).err(), Some(Error::Undecodable(0x104F)));
+
+        assert_eq!(make_pattern(&pe, 0x2000).err(), Some(Error::NotExecutable));
+        assert_eq!(make_pattern(&pe, 0x5000).err(), Some(Error::NotExecutable));
+    }
+
+    #[test]
+    fn to_macro_masks_and_captures() {
+        let pattern = Pattern {
+            bytes: &[(0x8B, 0xFF), (0x00, 0x00), (0x80, 0xF0)],
+            captures: &[Capture {
+                            name: "offset".into(),
+                            offset: 1,
+                        }],
+        };
+
+        assert_eq!(to_macro(pattern), "0x8B @offset ?? (0x80 & 0xF0)");
+        assert_eq!(to_macro(Pattern {
+                               bytes: &[],
+                               captures: &[],
+                           }),
+                   "");
+    }
+}